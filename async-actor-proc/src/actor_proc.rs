@@ -2,14 +2,14 @@ use proc_macro::TokenStream;
 use std::ops::Deref;
 
 use proc_macro2::TokenStream as TokenStream2;
-use quote::{format_ident, quote};
-use syn::{FnArg, ImplItem, ImplItemMethod, Item, ItemImpl, ItemStruct, Result, ReturnType, Visibility, VisPublic};
+use quote::{format_ident, quote, quote_spanned};
+use syn::{Attribute, Error, FnArg, GenericArgument, Ident, ImplItem, ImplItemMethod, Item, ItemImpl, ItemStruct, Pat, PathArguments, PathSegment, Result, ReturnType, Type, Visibility, VisPublic, WherePredicate};
 use syn::FnArg::Receiver;
-use syn::parse::Nothing;
+use syn::parse::Parser;
 use syn::punctuated::{Iter, Punctuated};
 use syn::token::{Async, Comma};
 
-use crate::util::{filter_function_parameters, format_data_name, format_function_parameter_definitions, format_function_parameter_names, format_generic_constraints, format_generic_definition, format_generic_usage, format_generic_usage_as_tuple, format_handle_self_ty, format_name, format_return_type, format_self_ty, merge_generics};
+use crate::util::{filter_function_parameters, format_data_name, format_function_parameter_definitions, format_function_parameter_names, format_generic_constraints, format_generic_definition, format_generic_usage, format_generic_usage_as_tuple, format_handle_self_ty, format_name, format_return_type, format_self_ty, merge_generic_constraints, merge_generics};
 
 pub fn actor_proc(args: TokenStream, input: TokenStream) -> TokenStream {
   let args = TokenStream2::from(args);
@@ -23,25 +23,185 @@ pub fn actor_proc(args: TokenStream, input: TokenStream) -> TokenStream {
 
 
 fn parse_and_expand(args: TokenStream2, input: TokenStream2) -> Result<TokenStream2> {
-  let item = parse(args, input)?;
-  expand(&item)
+  let (options, item) = parse(args, input)?;
+  expand(&options, &item)
 }
 
+#[derive(Default)]
+struct ActorOptions {
+  /// The option itself, so that errors about it point there.
+  concurrent_reads: Option<Ident>,
+}
+
+impl ActorOptions {
+  fn parse(args: TokenStream2) -> Result<Self> {
+    let mut options = Self::default();
 
-fn parse(args: TokenStream2, input: TokenStream2) -> Result<ItemImpl> {
-  syn::parse2::<Nothing>(args)?;
-  syn::parse2(input)
+    for option in Punctuated::<Ident, Comma>::parse_terminated.parse2(args)? {
+      match option.to_string().as_str() {
+        "concurrent_reads" => options.concurrent_reads = Some(option),
+        _ => return Err(Error::new_spanned(option, "expected `concurrent_reads`")),
+      }
+    }
+    Ok(options)
+  }
 }
 
-fn expand(original: &ItemImpl) -> Result<TokenStream2> {
-  let component_message_handler_impl = create_component_message_handler_impl(original)?;
+
+fn parse(args: TokenStream2, input: TokenStream2) -> Result<(ActorOptions, ItemImpl)> {
+  let options = ActorOptions::parse(args)?;
+  Ok((options, syn::parse2(input)?))
+}
+
+fn expand(options: &ActorOptions, original: &ItemImpl) -> Result<TokenStream2> {
+  check_parameter_names(original)?;
+  let component_message_handler_impl = create_component_message_handler_impl(options, original)?;
+  let original = strip_message_attributes(original);
   Ok(quote! {
     #original
     #(#component_message_handler_impl)*
   })
 }
 
-fn create_component_message_handler_impl(original: &ItemImpl) -> Result<Vec<Item>> {
+/// Message fields are named after the method parameters, so every parameter needs a plain name.
+fn check_parameter_names(original: &ItemImpl) -> Result<()> {
+  let inputs = original.items.iter()
+    .filter_map(|item| if let ImplItem::Method(method) = item { Some(method) } else { None })
+    .filter(|method| matches!(method.sig.inputs.first(), Some(Receiver(_))))
+    .flat_map(|method| method.sig.inputs.iter());
+
+  for input in inputs {
+    if let FnArg::Typed(typed) = input {
+      parameter_ident(&typed.pat)?;
+    }
+  }
+  Ok(())
+}
+
+fn is_message_attribute(attribute: &Attribute) -> bool {
+  attribute.path.is_ident("priority")
+}
+
+fn strip_message_attributes(original: &ItemImpl) -> ItemImpl {
+  let mut original = original.clone();
+  for item in original.items.iter_mut() {
+    if let ImplItem::Method(method) = item {
+      method.attrs.retain(|attribute| !is_message_attribute(attribute));
+    }
+  }
+  original
+}
+
+fn find_priority(original: &ItemImpl, function: &Ident) -> Result<Option<TokenStream2>> {
+  let attribute = original.items.iter()
+    .filter_map(|item| if let ImplItem::Method(method) = item { Some(method) } else { None })
+    .filter(|method| method.sig.ident == *function)
+    .flat_map(|method| method.attrs.iter())
+    .find(|attribute| attribute.path.is_ident("priority"));
+
+  let priority = match attribute {
+    None => return Ok(None),
+    Some(attribute) => attribute.parse_args::<Ident>()?,
+  };
+
+  match priority.to_string().as_str() {
+    "low" => Ok(Some(quote!(async_actor::system::Priority::Low))),
+    "normal" => Ok(Some(quote!(async_actor::system::Priority::Normal))),
+    "high" => Ok(Some(quote!(async_actor::system::Priority::High))),
+    _ => Err(Error::new_spanned(priority, "expected `low`, `normal` or `high`")),
+  }
+}
+
+fn is_read_only(original: &ItemImpl, function: &Ident) -> bool {
+  original.items.iter()
+    .filter_map(|item| if let ImplItem::Method(method) = item { Some(method) } else { None })
+    .filter(|method| method.sig.ident == *function)
+    .any(|method| matches!(method.sig.inputs.first(), Some(Receiver(receiver)) if receiver.mutability.is_none()))
+}
+
+/// `ctx: &mut Context<Self>` parameters are supplied by the runner instead of the caller.
+/// Other `Context` types are passed by the caller like any other parameter.
+fn is_context_parameter(input: &FnArg) -> bool {
+  match input {
+    FnArg::Typed(input) => match input.ty.deref() {
+      Type::Reference(reference) if reference.mutability.is_some() => match reference.elem.deref() {
+        Type::Path(path) if path.qself.is_none() => path.path.segments.last().map(is_context_of_self).unwrap_or(false),
+        _ => false,
+      },
+      _ => false,
+    },
+    FnArg::Receiver(_) => false,
+  }
+}
+
+fn is_context_of_self(segment: &PathSegment) -> bool {
+  if segment.ident != "Context" {
+    return false;
+  }
+
+  match &segment.arguments {
+    PathArguments::AngleBracketed(arguments) => matches!(
+      arguments.args.iter().collect::<Vec<_>>().as_slice(),
+      [GenericArgument::Type(Type::Path(ty))] if ty.qself.is_none() && ty.path.is_ident("Self")
+    ),
+    _ => false,
+  }
+}
+
+fn parameter_ident(pat: &Pat) -> Result<&Ident> {
+  match pat {
+    Pat::Ident(ident) => Ok(&ident.ident),
+    _ => Err(Error::new_spanned(pat, "expected a plain parameter name")),
+  }
+}
+
+/// Item type of a `ResponseSender<T>` parameter, which turns the method into a streaming one.
+fn find_response_item(input: &FnArg) -> Option<Type> {
+  let path = match input {
+    FnArg::Typed(input) => match input.ty.deref() {
+      Type::Path(path) => path,
+      _ => return None,
+    },
+    FnArg::Receiver(_) => return None,
+  };
+  let segment = path.path.segments.last().filter(|segment| segment.ident == "ResponseSender")?;
+
+  match &segment.arguments {
+    PathArguments::AngleBracketed(arguments) => arguments.args.iter().find_map(|argument| match argument {
+      GenericArgument::Type(ty) => Some(ty.clone()),
+      _ => None,
+    }),
+    _ => None,
+  }
+}
+
+fn is_streaming(function: &ImplItemMethod) -> bool {
+  function.sig.inputs.iter().any(|input| find_response_item(input).is_some())
+}
+
+/// Arguments the handler passes to the original method, with the runner's context in place of
+/// the context parameter.
+fn find_handler_arguments(original: &ItemImpl, function: &Ident) -> Result<Option<Vec<TokenStream2>>> {
+  let method = original.items.iter()
+    .filter_map(|item| if let ImplItem::Method(method) = item { Some(method) } else { None })
+    .find(|method| method.sig.ident == *function);
+
+  let method = match method {
+    Some(method) if method.sig.inputs.iter().any(is_context_parameter) => method,
+    _ => return Ok(None),
+  };
+
+  method.sig.inputs.iter()
+    .filter_map(|input| match input {
+      FnArg::Typed(_) if is_context_parameter(input) => Some(Ok(quote!(ctx))),
+      FnArg::Typed(typed) => Some(parameter_ident(&typed.pat).map(|ident| quote!(#ident))),
+      FnArg::Receiver(_) => None,
+    })
+    .collect::<Result<Vec<_>>>()
+    .map(Some)
+}
+
+fn create_component_message_handler_impl(options: &ActorOptions, original: &ItemImpl) -> Result<Vec<Item>> {
   let handle_name = format_handle_self_ty(&original.self_ty);
   let handle_name_unique = format_ident!("{}Unique", handle_name.clone().to_string());
   let generic_definition = format_generic_definition(&original.generics);
@@ -49,8 +209,10 @@ fn create_component_message_handler_impl(original: &ItemImpl) -> Result<Vec<Item
 
   let mut functions = create_wrapper_functions(original)?;
   let mut functions_data = create_wrapper_functions_data(original, &functions)?;
-  let mut function_handler = create_function_handler(original, &functions)?;
+  let mut function_handler = create_function_handler(options, original, &functions)?;
 
+  create_streaming_wrapper_functions(original, &mut functions)?;
+  functions.append(&mut create_try_wrapper_functions(original)?);
   for function in &mut functions {
     function.sig.asyncness = Some(Default::default());
   }
@@ -75,7 +237,7 @@ fn create_component_message_handler_impl(original: &ItemImpl) -> Result<Vec<Item
   Ok(result)
 }
 
-fn create_function_handler(original: &ItemImpl, functions: &Vec<ImplItemMethod>) -> Result<Vec<Item>> {
+fn create_function_handler(options: &ActorOptions, original: &ItemImpl, functions: &Vec<ImplItemMethod>) -> Result<Vec<Item>> {
   let mut result = vec![];
 
   let original_name = format_self_ty(&original.self_ty);
@@ -96,19 +258,62 @@ fn create_function_handler(original: &ItemImpl, functions: &Vec<ImplItemMethod>)
       None => quote!(),
       Some(_) => quote!(.await)
     };
+    let priority = find_priority(original, &function.sig.ident)?.into_iter();
+    let handler_arguments = find_handler_arguments(original, &function.sig.ident)?;
+    let context = if handler_arguments.is_some() { quote!(ctx) } else { quote!(_ctx) };
+    let arguments = match &handler_arguments {
+      Some(arguments) => quote!(#(#arguments,)*),
+      None => parameter_names.clone(),
+    };
+    let shared = options.concurrent_reads.is_some() && handler_arguments.is_none() && is_read_only(original, &function.sig.ident);
+    let shared_dispatch = shared.then(|| quote! {
+      const SHARED_DISPATCH: core::option::Option<async_actor::system::ComponentMessageSharedDispatchFn<Self>> =
+        core::option::Option::Some(<Self as async_actor::system::ComponentMessageReader<#data_name #merged_generic_definition>>::dispatch_shared);
+    });
 
     result.push(Item::Impl(syn::parse2(quote! {
-      #[async_trait::async_trait]
       impl #merged_generic_definition async_actor::system::ComponentMessageHandler<#data_name #merged_generic_definition> for #original_name #generic_definition #merged_generic_constraints {
         type Answer = #return_name;
 
-        async fn handle(&mut self, request: #data_name #merged_generic_definition) -> Self::Answer {
-          let #data_name #merged_generic_usage { #parameter_names .. } = request;
-          self.#function_name #function_generic_usage (#parameter_names)#await_maybe
+        #(const PRIORITY: async_actor::system::Priority = #priority;)*
+
+        #shared_dispatch
+
+        fn handle<'handler>(
+          &'handler mut self,
+          #context: &'handler mut async_actor::system::Context<Self>,
+          request: #data_name #merged_generic_definition,
+        ) -> async_actor::system::ModeFuture<'handler, Self, Self::Answer> {
+          std::boxed::Box::pin(async move {
+            let #data_name #merged_generic_usage { #parameter_names .. } = request;
+            self.#function_name #function_generic_usage (#arguments)#await_maybe
+          })
         }
 
       }
     })?));
+
+    if let Some(concurrent_reads) = options.concurrent_reads.as_ref().filter(|_| shared) {
+      let sync_check = quote_spanned! {concurrent_reads.span()=>
+        fn concurrent_reads<C>()
+          where
+            C: async_actor::system::Component,
+            C::Mode: async_actor::system::ConcurrentReads<C>,
+        {}
+        concurrent_reads::<Self>();
+      };
+      result.push(Item::Impl(syn::parse2(quote! {
+        impl #merged_generic_definition async_actor::system::ComponentMessageReader<#data_name #merged_generic_definition> for #original_name #generic_definition #merged_generic_constraints {
+          fn handle_shared(&self, request: #data_name #merged_generic_definition) -> async_actor::system::ModeFuture<'_, Self, Self::Answer> {
+            #sync_check
+            std::boxed::Box::pin(async move {
+              let #data_name #merged_generic_usage { #parameter_names .. } = request;
+              self.#function_name #function_generic_usage (#parameter_names)#await_maybe
+            })
+          }
+        }
+      })?));
+    }
   }
   Ok(result)
 }
@@ -145,6 +350,31 @@ fn create_wrapper_functions_data(original: &ItemImpl, functions: &Vec<ImplItemMe
         }
       }
     })?));
+
+    // The higher-ranked bounds keep the impl valid for parameters that are not `Clone`.
+    let mut clone_constraints = vec![];
+    for parameter in &parameters {
+      let ty = &parameter.ty;
+      clone_constraints.push(syn::parse2::<WherePredicate>(quote!(for<'__clone> #ty: core::clone::Clone))?);
+    }
+    let existing_constraints = merged_generics.where_clause.iter()
+      .flat_map(|where_clause| where_clause.predicates.iter().cloned())
+      .collect();
+    let clone_constraints = merge_generic_constraints(vec![existing_constraints, clone_constraints]);
+    let parameter_idents = parameters.iter()
+      .map(|parameter| parameter_ident(&parameter.pat))
+      .collect::<Result<Vec<_>>>()?;
+
+    data.push(Item::Impl(syn::parse2(quote! {
+      impl #generic_definition core::clone::Clone for #data_name #generic_definition #clone_constraints {
+        fn clone(&self) -> Self {
+          Self {
+            _phantom: core::default::Default::default(),
+            #(#parameter_idents: core::clone::Clone::clone(&self.#parameter_idents),)*
+          }
+        }
+      }
+    })?));
   }
 
   Ok(data)
@@ -163,15 +393,75 @@ fn create_wrapper_functions(original: &ItemImpl) -> Result<Vec<ImplItemMethod>>
     let merged_generics = merge_generics(vec![function.sig.generics.clone(), original.generics.clone()]);
     let generic_usage = format_generic_usage(&merged_generics);
     let data_name = format_data_name(&original_name, &function.sig.ident);
+    function.sig.inputs = function.sig.inputs.iter()
+      .filter(|input| !is_context_parameter(input))
+      .cloned()
+      .collect();
     let parameter_names = format_function_parameter_names(&function.sig.inputs.iter());
 
     if let Some(Receiver(receiver)) = function.sig.inputs.first_mut() {
       receiver.mutability = None;
     }
+    function.attrs.retain(|attribute| !is_message_attribute(attribute));
     function.block = syn::parse2(quote! {{
       self.inner.dispatch(#data_name #generic_usage ::new(#parameter_names)).await
     }})?;
     function.vis = Visibility::Public(VisPublic { pub_token: Default::default() })
   }
   Ok(functions)
+}
+
+/// Streaming methods are dispatched with a fresh `ResponseSender` and hand its stream to the caller.
+fn create_streaming_wrapper_functions(original: &ItemImpl, functions: &mut [ImplItemMethod]) -> Result<()> {
+  let original_name = format_self_ty(&original.self_ty);
+
+  for function in functions.iter_mut().filter(|function| is_streaming(function)) {
+    let merged_generics = merge_generics(vec![function.sig.generics.clone(), original.generics.clone()]);
+    let generic_usage = format_generic_usage(&merged_generics);
+    let data_name = format_data_name(&original_name, &function.sig.ident);
+    let parameter_names = format_function_parameter_names(&function.sig.inputs.iter());
+
+    let response = function.sig.inputs.iter().find(|input| find_response_item(input).is_some()).cloned().unwrap();
+    let item = find_response_item(&response).unwrap();
+    let response_name = match &response {
+      FnArg::Typed(typed) => parameter_ident(&typed.pat)?.clone(),
+      FnArg::Receiver(_) => unreachable!(),
+    };
+
+    function.sig.inputs = function.sig.inputs.iter()
+      .filter(|input| find_response_item(input).is_none())
+      .cloned()
+      .collect();
+    function.sig.output = syn::parse2(quote! {
+      -> async_actor::system::ResponseStream<#item>
+    })?;
+    function.block = syn::parse2(quote! {{
+      self.inner.dispatch_stream(|#response_name| #data_name #generic_usage ::new(#parameter_names)).await
+    }})?;
+  }
+  Ok(())
+}
+
+fn create_try_wrapper_functions(original: &ItemImpl) -> Result<Vec<ImplItemMethod>> {
+  let mut functions = create_wrapper_functions(original)?;
+  functions.retain(|function| !is_streaming(function));
+
+  let original_name = format_self_ty(&original.self_ty);
+
+  for function in functions.iter_mut() {
+    let merged_generics = merge_generics(vec![function.sig.generics.clone(), original.generics.clone()]);
+    let generic_usage = format_generic_usage(&merged_generics);
+    let data_name = format_data_name(&original_name, &function.sig.ident);
+    let parameter_names = format_function_parameter_names(&function.sig.inputs.iter());
+    let return_name = format_return_type(&function.sig.output);
+
+    function.sig.ident = format_ident!("try_{}", function.sig.ident);
+    function.sig.output = syn::parse2(quote! {
+      -> core::result::Result<#return_name, async_actor::system::DispatchError>
+    })?;
+    function.block = syn::parse2(quote! {{
+      self.inner.try_dispatch(#data_name #generic_usage ::new(#parameter_names)).await
+    }})?;
+  }
+  Ok(functions)
 }
\ No newline at end of file
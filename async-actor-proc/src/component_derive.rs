@@ -1,10 +1,10 @@
 use proc_macro::TokenStream;
 use std::vec;
-use syn::{Generics, Item, ItemImpl, ItemStruct, PredicateType, Result, Token, WhereClause, WherePredicate};
-use quote::{quote};
+use syn::{Error, Generics, Item, ItemImpl, ItemStruct, Lit, Meta, NestedMeta, PredicateType, Result, Token, WhereClause, WherePredicate};
+use quote::{quote, ToTokens};
 use proc_macro2::{TokenStream as TokenStream2};
 use syn::punctuated::Punctuated;
-use crate::util::{format_generic_constraints, format_generic_definition, format_generic_usage, format_handle_name, format_handle_name_unique, format_name, merge_generic_constraints, merge_generics};
+use crate::util::{format_generic_constraints, format_generic_definition, format_generic_usage, format_handle_name, format_handle_name_unique, format_name, format_weak_handle_name, merge_generic_constraints, merge_generics};
 
 pub fn component_derive(input: TokenStream) -> TokenStream {
   let input = TokenStream2::from(input);
@@ -26,20 +26,45 @@ fn parse(input: TokenStream2) -> Result<ItemStruct> {
 }
 
 fn expand(mut original: &ItemStruct) -> Result<TokenStream2> {
-  let component_impl = create_component_impl(original)?;
-  let component_handle = create_component_handle(original)?;
+  let options = ComponentOptions::parse(original)?;
+  let component_impl = create_component_impl(original, &options)?;
+  let component_handle = create_component_handle(original, &options)?;
   Ok(quote! {
     #(#component_handle)*
     #(#component_impl)*
   })
 }
 
-fn create_component_handle(original: &ItemStruct) -> Result<Vec<Item>> {
+fn create_component_handle(original: &ItemStruct, options: &ComponentOptions) -> Result<Vec<Item>> {
   let original_name = format_name(&original.ident);
   let handle_name = format_handle_name(&original.ident);
   let handle_name_unique = format_handle_name_unique(&original.ident);
+  let weak_handle_name = format_weak_handle_name(&original.ident);
   let generic_definition = format_generic_definition(&original.generics);
   let generic_constraints = format_generic_constraints(&original.generics);
+  // Local components are not `Send`, so they cannot be handed to the caller
+  let (stop_and_take, stop_and_take_unique) = if options.local {
+    (quote!(), quote!())
+  } else {
+    (
+      quote! {
+        pub async fn stop_and_take(&self) -> Option<#original_name #generic_definition>
+          where
+            #original_name #generic_definition: Send,
+        {
+          self.inner.stop_and_take().await
+        }
+      },
+      quote! {
+        pub async fn stop_and_take(self) -> Option<#original_name #generic_definition>
+          where
+            #original_name #generic_definition: Send,
+        {
+          self.inner.stop_and_take().await
+        }
+      },
+    )
+  };
 
   Ok(vec![
     Item::Struct(syn::parse2::<ItemStruct>(quote! {
@@ -53,6 +78,12 @@ fn create_component_handle(original: &ItemStruct) -> Result<Vec<Item>> {
         inner: async_actor::system::ComponentHandleUnique<#original_name #generic_definition>,
       }
     })?),
+    Item::Struct(syn::parse2::<ItemStruct>(quote! {
+      #[derive(Clone)]
+      pub struct #weak_handle_name #generic_definition #generic_constraints {
+        inner: async_actor::system::WeakComponentHandle<#original_name #generic_definition>,
+      }
+    })?),
     // Item::Impl(syn::parse2::<ItemImpl>(quote! {
     //   impl<T> #generic_definition async_actor::system::EnsureNotDroppedForDuration for T #handle_name_unique #generic_definition #ensure_not_dropped_generic_constraints  {
     //
@@ -71,35 +102,275 @@ fn create_component_handle(original: &ItemStruct) -> Result<Vec<Item>> {
     // })?),
     Item::Impl(syn::parse2(quote! {
       impl #generic_definition #handle_name #generic_definition #generic_constraints {
-        pub fn to_unique(self) -> #handle_name_unique #generic_definition {
-          self.into()
+        pub fn to_unique(self) -> core::result::Result<#handle_name_unique #generic_definition, Self> {
+          core::convert::TryInto::try_into(self)
+        }
+
+        pub fn downgrade(&self) -> #weak_handle_name #generic_definition {
+          #weak_handle_name { inner: self.inner.downgrade() }
+        }
+
+        pub fn stop(&self) {
+          self.inner.stop()
+        }
+
+        pub fn abort(&self) {
+          self.inner.abort()
+        }
+
+        pub fn with_timeout(self, timeout: std::time::Duration) -> Self {
+          Self { inner: self.inner.with_timeout(timeout) }
+        }
+
+        pub async fn terminated(&self) {
+          self.inner.terminated().await
+        }
+
+        #stop_and_take
+
+        pub fn attach_stream<S>(&self, stream: S) -> async_actor::system::StreamAttachment
+          where
+            S: async_actor::system::Stream + Send + 'static,
+            S::Item: Send + 'static,
+            #original_name #generic_definition: async_actor::system::ComponentMessageHandler<S::Item>,
+        {
+          self.inner.attach_stream(stream)
+        }
+
+        pub fn attach_receiver<M>(&self, receiver: tokio::sync::mpsc::Receiver<M>) -> async_actor::system::StreamAttachment
+          where
+            M: Send + 'static,
+            #original_name #generic_definition: async_actor::system::ComponentMessageHandler<M>,
+        {
+          self.inner.attach_receiver(receiver)
+        }
+
+        pub fn make_sender<M>(&self) -> async_actor::system::MessageSender<M, <#original_name #generic_definition as async_actor::system::ComponentMessageHandler<M>>::Answer>
+          where
+            M: Send + 'static,
+            #original_name #generic_definition: async_actor::system::ComponentMessageHandler<M>,
+        {
+          self.inner.make_sender()
+        }
+
+        pub fn make_transforming_sender<M, N, T>(
+          &self,
+          transformer: T,
+        ) -> async_actor::system::MessageSender<M, <#original_name #generic_definition as async_actor::system::ComponentMessageHandler<N>>::Answer>
+          where
+            M: Send + 'static,
+            N: Send + 'static,
+            T: Fn(M) -> N + Send + Sync + 'static,
+            #original_name #generic_definition: async_actor::system::ComponentMessageHandler<N>,
+        {
+          self.inner.make_transforming_sender(transformer)
+        }
+      }
+    })?),
+    Item::Impl(syn::parse2(quote! {
+      impl #generic_definition #handle_name_unique #generic_definition #generic_constraints {
+        pub fn stop(&self) {
+          self.inner.stop()
+        }
+
+        pub fn abort(&self) {
+          self.inner.abort()
+        }
+
+        pub fn with_timeout(self, timeout: std::time::Duration) -> Self {
+          Self { inner: self.inner.with_timeout(timeout) }
+        }
+
+        pub async fn terminated(&self) {
+          self.inner.terminated().await
+        }
+
+        #stop_and_take_unique
+
+        pub fn attach_stream<S>(&self, stream: S) -> async_actor::system::StreamAttachment
+          where
+            S: async_actor::system::Stream + Send + 'static,
+            S::Item: Send + 'static,
+            #original_name #generic_definition: async_actor::system::ComponentMessageHandler<S::Item>,
+        {
+          self.inner.attach_stream(stream)
+        }
+
+        pub fn attach_receiver<M>(&self, receiver: tokio::sync::mpsc::Receiver<M>) -> async_actor::system::StreamAttachment
+          where
+            M: Send + 'static,
+            #original_name #generic_definition: async_actor::system::ComponentMessageHandler<M>,
+        {
+          self.inner.attach_receiver(receiver)
+        }
+
+        pub fn make_sender<M>(&self) -> async_actor::system::MessageSender<M, <#original_name #generic_definition as async_actor::system::ComponentMessageHandler<M>>::Answer>
+          where
+            M: Send + 'static,
+            #original_name #generic_definition: async_actor::system::ComponentMessageHandler<M>,
+        {
+          self.inner.make_sender()
+        }
+
+        pub fn make_transforming_sender<M, N, T>(
+          &self,
+          transformer: T,
+        ) -> async_actor::system::MessageSender<M, <#original_name #generic_definition as async_actor::system::ComponentMessageHandler<N>>::Answer>
+          where
+            M: Send + 'static,
+            N: Send + 'static,
+            T: Fn(M) -> N + Send + Sync + 'static,
+            #original_name #generic_definition: async_actor::system::ComponentMessageHandler<N>,
+        {
+          self.inner.make_transforming_sender(transformer)
+        }
+
+        pub fn downgrade(&self) -> #weak_handle_name #generic_definition {
+          #weak_handle_name { inner: self.inner.downgrade() }
+        }
+      }
+    })?),
+    Item::Impl(syn::parse2(quote! {
+      impl #generic_definition #weak_handle_name #generic_definition #generic_constraints {
+        pub fn upgrade(&self) -> Option<#handle_name #generic_definition> {
+          self.inner.upgrade().map(|inner| #handle_name { inner })
         }
       }
     })?),
     Item::Impl(syn::parse2::<ItemImpl>(quote! {
-      impl #generic_definition core::convert::From<#handle_name #generic_definition> for #handle_name_unique #generic_definition #generic_constraints {
-        fn from(value: #handle_name #generic_definition) -> Self {
-          Self {
-            inner: value.inner.into()
-          }
+      impl #generic_definition core::convert::TryFrom<#handle_name #generic_definition> for #handle_name_unique #generic_definition #generic_constraints {
+        type Error = #handle_name #generic_definition;
+
+        fn try_from(value: #handle_name #generic_definition) -> core::result::Result<Self, Self::Error> {
+          core::convert::TryInto::try_into(value.inner)
+            .map(|inner| Self { inner })
+            .map_err(|inner| #handle_name { inner })
         }
       }
     })?),
   ])
 }
 
-fn create_component_impl(original: &ItemStruct) -> Result<Vec<ItemImpl>> {
+#[derive(Default)]
+pub(crate) struct ComponentOptions {
+  lifecycle: bool,
+  pub(crate) local: bool,
+  panic_policy: Option<TokenStream2>,
+  mailbox_capacity: Option<TokenStream2>,
+  overflow_policy: Option<TokenStream2>,
+  runner: Option<TokenStream2>,
+}
+
+impl ComponentOptions {
+  pub(crate) fn parse(original: &ItemStruct) -> Result<Self> {
+    let mut options = Self::default();
+    for attribute in original.attrs.iter().filter(|attr| attr.path.is_ident("component")) {
+      let list = match attribute.parse_meta()? {
+        Meta::List(list) => list,
+        meta => return Err(Error::new_spanned(meta, "expected #[component(...)]")),
+      };
+      for nested in list.nested {
+        match nested {
+          NestedMeta::Meta(Meta::Path(path)) if path.is_ident("lifecycle") => options.lifecycle = true,
+          NestedMeta::Meta(Meta::Path(path)) if path.is_ident("local") => options.local = true,
+          NestedMeta::Meta(Meta::NameValue(name_value)) if name_value.path.is_ident("on_panic") => {
+            options.panic_policy = Some(match &name_value.lit {
+              Lit::Str(policy) if policy.value() == "resume" => quote!(async_actor::system::PanicPolicy::Resume),
+              Lit::Str(policy) if policy.value() == "stop" => quote!(async_actor::system::PanicPolicy::Stop),
+              lit => return Err(Error::new_spanned(lit, "expected \"resume\" or \"stop\"")),
+            });
+          }
+          NestedMeta::Meta(Meta::NameValue(name_value)) if name_value.path.is_ident("mailbox") => {
+            options.mailbox_capacity = Some(match &name_value.lit {
+              Lit::Int(capacity) if capacity.base10_parse::<usize>()? == 0 => {
+                return Err(Error::new_spanned(capacity, "mailbox capacity must be at least 1"));
+              }
+              Lit::Int(capacity) => quote!(core::option::Option::Some(#capacity)),
+              lit => return Err(Error::new_spanned(lit, "expected the mailbox capacity")),
+            });
+          }
+          NestedMeta::Meta(Meta::NameValue(name_value)) if name_value.path.is_ident("overflow") => {
+            options.overflow_policy = Some(match &name_value.lit {
+              Lit::Str(policy) if policy.value() == "block" => quote!(async_actor::system::OverflowPolicy::Block),
+              Lit::Str(policy) if policy.value() == "reject" => quote!(async_actor::system::OverflowPolicy::Reject),
+              Lit::Str(policy) if policy.value() == "drop_newest" => quote!(async_actor::system::OverflowPolicy::DropNewest),
+              Lit::Str(policy) if policy.value() == "drop_oldest" => quote!(async_actor::system::OverflowPolicy::DropOldest),
+              Lit::Str(policy) if policy.value() == "keep_latest" => quote!(async_actor::system::OverflowPolicy::KeepLatest),
+              lit => return Err(Error::new_spanned(lit, "expected \"block\", \"reject\", \"drop_newest\", \"drop_oldest\" or \"keep_latest\"")),
+            });
+          }
+          NestedMeta::Meta(Meta::NameValue(name_value)) if name_value.path.is_ident("runner") => {
+            options.runner = Some(match &name_value.lit {
+              Lit::Str(runner) => runner.parse::<syn::Type>()?.into_token_stream(),
+              lit => return Err(Error::new_spanned(lit, "expected the runner type")),
+            });
+          }
+          nested => return Err(Error::new_spanned(nested, "unknown component option")),
+        }
+      }
+    }
+    Ok(options)
+  }
+}
+
+fn create_component_constants(options: &ComponentOptions) -> TokenStream2 {
+  let panic_policy = options.panic_policy.iter();
+  let mailbox_capacity = options.mailbox_capacity.iter();
+  let overflow_policy = options.overflow_policy.iter();
+  let runner = options.runner.iter();
+  let mode = if options.local {
+    quote!(async_actor::system::Local)
+  } else {
+    quote!(async_actor::system::Threaded)
+  };
+
+  quote! {
+    type Mode = #mode;
+    #(type Runner = #runner;)*
+    #(const PANIC_POLICY: async_actor::system::PanicPolicy = #panic_policy;)*
+    #(const MAILBOX_CAPACITY: core::option::Option<usize> = #mailbox_capacity;)*
+    #(const OVERFLOW_POLICY: async_actor::system::OverflowPolicy = #overflow_policy;)*
+  }
+}
+
+fn create_lifecycle_functions(options: &ComponentOptions) -> TokenStream2 {
+  if !options.lifecycle {
+    return quote!();
+  }
+
+  quote! {
+    fn started(&mut self) -> async_actor::system::ModeFuture<'_, Self, ()> {
+      std::boxed::Box::pin(async_actor::system::ComponentLifecycle::on_started(self))
+    }
+
+    fn stopping(&mut self) -> async_actor::system::ModeFuture<'_, Self, ()> {
+      std::boxed::Box::pin(async_actor::system::ComponentLifecycle::on_stopping(self))
+    }
+
+    fn stopped(self) -> async_actor::system::ModeFuture<'static, Self, ()> {
+      std::boxed::Box::pin(async_actor::system::ComponentLifecycle::on_stopped(self))
+    }
+  }
+}
+
+fn create_component_impl(original: &ItemStruct, options: &ComponentOptions) -> Result<Vec<ItemImpl>> {
   let original_name = format_name(&original.ident);
   let handle_name = format_handle_name(&original.ident);
   let generic_definition = format_generic_definition(&original.generics);
   let generic_constraints = format_generic_constraints(&original.generics);
+  let component_constants = create_component_constants(options);
+  let lifecycle_functions = create_lifecycle_functions(options);
 
   Ok(vec![
     syn::parse2(quote! {
       impl #generic_definition  async_actor::system::Component for #original_name  #generic_definition #generic_constraints {
+        #component_constants
+
         fn create_wrapper(inner: async_actor::system::ComponentHandle<Self>) -> Self::HandleWrapper {
           Self::HandleWrapper { inner }
         }
+
+        #lifecycle_functions
       }
     })?,
     syn::parse2(quote! {
@@ -107,5 +378,35 @@ fn create_component_impl(original: &ItemStruct) -> Result<Vec<ItemImpl>> {
         type HandleWrapper = #handle_name #generic_definition;
       }
     })?,
+    syn::parse2(quote! {
+      impl #generic_definition async_actor::system::ComponentHandleWrapper for #handle_name #generic_definition #generic_constraints {
+        type Component = #original_name #generic_definition;
+
+        fn component_handle(&self) -> &async_actor::system::ComponentHandle<Self::Component> {
+          &self.inner
+        }
+      }
+    })?,
   ])
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn mailbox_capacity_must_be_at_least_one() {
+    let empty: ItemStruct = syn::parse_quote! {
+      #[component(mailbox = 0)]
+      struct Empty;
+    };
+    let bounded: ItemStruct = syn::parse_quote! {
+      #[component(mailbox = 1)]
+      struct Bounded;
+    };
+
+    let Err(error) = ComponentOptions::parse(&empty) else { panic!("capacity 0 was accepted") };
+    assert_eq!(error.to_string(), "mailbox capacity must be at least 1");
+    assert!(ComponentOptions::parse(&bounded).is_ok());
+  }
+}
@@ -1,7 +1,8 @@
 use proc_macro::TokenStream;
-use syn::{Field, Fields, FieldsNamed, ItemImpl, ItemStruct, Result};
+use syn::{Error, Field, Fields, FieldsNamed, ItemImpl, ItemStruct, Result};
 use quote::{format_ident, quote};
 use proc_macro2::{TokenStream as TokenStream2};
+use crate::component_derive::ComponentOptions;
 use crate::util::{format_generic_constraints, format_generic_definition, format_handle_name, format_injectable_struct_instantiation, format_name};
 
 pub fn injectable_instance_derive(input: TokenStream) -> TokenStream {
@@ -23,6 +24,7 @@ fn parse(input: TokenStream2) -> Result<ItemStruct> {
 }
 
 fn expand(mut original: &ItemStruct) -> Result<TokenStream2> {
+  check_not_local(original)?;
   let original_name = format_name(&original.ident);
   let handle_name = format_handle_name(&original.ident);
   let generic_definition = format_generic_definition(&original.generics);
@@ -35,9 +37,51 @@ fn expand(mut original: &ItemStruct) -> Result<TokenStream2> {
 
       fn create_instance(injector: async_actor::inject::Injector) -> core::pin::Pin<Box<dyn core::future::Future<Output=std::boxed::Box<Self>> + core::marker::Send + core::marker::Sync>> {
         std::boxed::Box::pin(async move {
-          Box::new(async_actor::system::Component::start(#instantiation))
+          let component = #instantiation;
+          Box::new(injector.system().spawn(component))
         })
       }
+
+      fn stop_instance(&self) -> core::pin::Pin<Box<dyn core::future::Future<Output=()> + core::marker::Send + '_>> {
+        std::boxed::Box::pin(async move {
+          self.stop();
+          self.terminated().await;
+        })
+      }
+
+      fn is_terminated(&self) -> bool {
+        async_actor::system::ComponentHandleWrapper::component_handle(self).is_terminated()
+      }
     }
   })
 }
+
+/// The injector spawns components on any worker thread, which local components must not leave.
+fn check_not_local(original: &ItemStruct) -> Result<()> {
+  if ComponentOptions::parse(original)?.local {
+    return Err(Error::new_spanned(&original.ident, "local components cannot be injected, start them with `start_local`"));
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn local_components_are_rejected() {
+    let local: ItemStruct = syn::parse_quote! {
+      #[component(local)]
+      struct Local;
+    };
+    let malformed: ItemStruct = syn::parse_quote! {
+      #[component(local, unknown)]
+      struct Malformed;
+    };
+
+    let Err(error) = expand(&local) else { panic!("local component was accepted") };
+    assert_eq!(error.to_string(), "local components cannot be injected, start them with `start_local`");
+    let Err(error) = expand(&malformed) else { panic!("malformed attribute was accepted") };
+    assert_eq!(error.to_string(), "unknown component option");
+  }
+}
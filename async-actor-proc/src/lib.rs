@@ -24,7 +24,7 @@ pub fn assisted_instantiable(input: TokenStream) -> TokenStream {
   assisted_instantiable_derive::assisted_instantiable_derive(input)
 }
 
-#[proc_macro_derive(Component)]
+#[proc_macro_derive(Component, attributes(component))]
 pub fn component_derive(input: TokenStream) -> TokenStream {
   component_derive::component_derive(input)
 }
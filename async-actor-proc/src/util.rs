@@ -53,6 +53,10 @@ pub fn format_handle_name_unique(ident: &Ident) -> TokenStream2 {
     format_name(&format_ident!("{}HandleUnique", ident))
 }
 
+pub fn format_weak_handle_name(ident: &Ident) -> TokenStream2 {
+    format_name(&format_ident!("{}WeakHandle", ident))
+}
+
 pub fn format_impl_name(ident: &Ident) -> TokenStream2 {
     format_name(&format_ident!("{}Impl", ident))
 }
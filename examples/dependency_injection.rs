@@ -1,4 +1,5 @@
 use async_actor::inject::{Injector};
+use async_actor::system::ComponentLifecycle;
 use async_actor_proc::{actor, Component, inject, Injectable};
 
 
@@ -41,8 +42,16 @@ impl UserService {
 
 
 #[derive(Component, Injectable)]
+#[component(lifecycle)]
 pub struct DatabaseService {
-  connected: bool,
+  #[inject_default] connected: bool,
+}
+
+impl ComponentLifecycle for DatabaseService {
+  async fn on_started(&mut self) {
+    // Connect once on boot instead of lazily on the first request
+    self.establish_connection().await;
+  }
 }
 
 #[actor]
@@ -57,13 +66,7 @@ impl DatabaseService {
   }
 
   pub async fn save_user(&mut self, user: String) {
-    // Ensure service is connected to database
-    if !self.is_connected().await {
-      println!("Database connection not yet established.. Starting connection process..");
-      self.establish_connection().await;
-    } else {
-      println!("Database connection already established");
-    }
+    assert!(self.is_connected().await, "started hook establishes the connection");
 
     println!("Saved user: {}", user);
   }
@@ -0,0 +1,92 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use async_actor_proc::{actor, Component, Injectable};
+use crate::system::{DispatchError, MessageSender, WeakMessageSender};
+
+/// Delivers published events to every subscriber of the event type.
+///
+/// Subscribers are held weakly, so subscribing does not keep a component running. Subscribers that stopped are
+/// dropped on the next `subscribe` or `publish`.
+#[derive(Default, Component, Injectable)]
+pub struct EventBus {
+  #[inject_default] subscribers: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+}
+
+#[actor]
+impl EventBus {
+  pub async fn subscribe<E>(&mut self, subscriber: MessageSender<E, ()>)
+    where
+      E: Clone + Send + 'static,
+  {
+    let subscribers = self.subscribers_of::<E>();
+    subscribers.retain(|subscriber| !subscriber.is_closed());
+    subscribers.push(subscriber.downgrade());
+  }
+
+  /// Sends `event` to all live subscribers without waiting for them, returns how many received it.
+  pub async fn publish<E>(&mut self, event: E) -> usize
+    where
+      E: Clone + Send + 'static,
+  {
+    let mut delivered = 0;
+
+    self.subscribers_of::<E>().retain(|subscriber| {
+      match subscriber.try_dispatch_sync_nowait(event.clone()) {
+        Ok(()) => {
+          delivered += 1;
+          true
+        }
+        Err(DispatchError::MailboxClosed) => false,
+        Err(_) => true,
+      }
+    });
+
+    delivered
+  }
+}
+
+impl EventBus {
+  fn subscribers_of<E>(&mut self) -> &mut Vec<WeakMessageSender<E, ()>>
+    where
+      E: Send + 'static,
+  {
+    self.subscribers.entry(TypeId::of::<E>())
+      .or_insert_with(|| Box::new(Vec::<WeakMessageSender<E, ()>>::new()))
+      .downcast_mut()
+      .unwrap()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::system::Component;
+
+  #[derive(Component)]
+  pub struct Inbox {
+    events: Vec<u32>,
+  }
+
+  #[actor]
+  impl Inbox {
+    pub async fn receive(&mut self, event: u32) {
+      self.events.push(event)
+    }
+  }
+
+  #[tokio::test]
+  async fn stopped_subscribers_are_dropped() {
+    let bus = EventBus::default().start();
+    let first = Inbox { events: vec![] }.start();
+    let second = Inbox { events: vec![] }.start();
+
+    bus.subscribe(first.make_transforming_sender(InboxReceiveData::new)).await;
+    bus.subscribe(second.make_transforming_sender(InboxReceiveData::new)).await;
+    assert_eq!(bus.publish(1u32).await, 2);
+
+    second.stop();
+    second.terminated().await;
+    assert_eq!(bus.publish(2u32).await, 1);
+    assert_eq!(first.stop_and_take().await.unwrap().events, vec![1, 2]);
+  }
+}
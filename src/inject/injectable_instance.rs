@@ -7,6 +7,16 @@ use crate::system::{Component, HasHandleWrapper};
 pub trait InjectableInstance where Self: Any + Send + Sync {
   type Inner: HasHandleWrapper<HandleWrapper=Self>;
   fn create_instance(injector: Injector) -> Pin<Box<dyn Future<Output=Box<Self>> + Send + Sync>>;
+
+  /// Stops the instance and waits until it terminated, see [`Injector::shutdown`].
+  fn stop_instance(&self) -> Pin<Box<dyn Future<Output=()> + Send + '_>> {
+    Box::pin(async {})
+  }
+
+  /// Whether the instance stopped on its own, the injector then creates it anew.
+  fn is_terminated(&self) -> bool {
+    false
+  }
 }
 
 pub trait ManuallyInjectableInstance where Self: Any + Send + Sync {
@@ -1,5 +1,6 @@
 use std::any::{Any, type_name, TypeId};
-use std::collections::{HashMap};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
 use std::future::Future;
 use std::marker::Unsize;
 use std::ops::Deref;
@@ -9,7 +10,7 @@ use async_lock::{RwLock, RwLockUpgradableReadGuard};
 use async_actor_proc::{actor, Component, Injectable};
 use crate as async_actor;
 use crate::inject::injectable_instance::InjectableInstance;
-use crate::system::{Component, HasHandleWrapper};
+use crate::system::{ActorSystem, Component, HasHandleWrapper};
 use crate::util::lazy_cell::LazyCell;
 
 pub mod injectable_instance;
@@ -21,16 +22,56 @@ enum Binding {
   Named(TypeId, String),
 }
 
+tokio::task_local! {
+  /// Binding whose instance is being created by the current task.
+  static CREATING: Binding;
+}
+
+type StopInstanceFn = Box<dyn Fn() -> Pin<Box<dyn Future<Output=()> + Send>> + Send + Sync>;
+
+struct CreatedInstance {
+  name: &'static str,
+  stop: StopInstanceFn,
+}
+
 #[derive(Default)]
 pub struct InjectorInner {
   injected_instances: HashMap<Binding, Arc<dyn Any + Send + Sync>>,
   loading_injected_instances: HashMap<Binding, String>,
   mappings: HashMap<TypeId, TypeId>,
+  created_instances: HashMap<Binding, CreatedInstance>,
+  dependencies: HashMap<Binding, HashSet<Binding>>,
+}
+
+impl InjectorInner {
+  fn record_dependency(&mut self, binding: &Binding) {
+    if let Ok(dependent) = CREATING.try_with(Binding::clone) {
+      self.dependencies.entry(dependent).or_default().insert(binding.clone());
+    }
+  }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ShutdownError {
+  /// The instances depend on each other and were left running.
+  Cycle(Vec<&'static str>),
+}
+
+impl Display for ShutdownError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      ShutdownError::Cycle(names) => write!(f, "instances depend on each other: {}", names.join(", ")),
+    }
+  }
+}
+
+impl std::error::Error for ShutdownError {}
+
 #[derive(Default, Clone, Component)]
 pub struct Injector {
   inner: Arc<RwLock<InjectorInner>>,
+  system: ActorSystem,
 }
 
 impl InjectableInstance for InjectorHandle {
@@ -81,6 +122,16 @@ impl Injector {
 }
 
 impl Injector {
+  /// Injector spawning the instances it creates on `system`.
+  pub fn new(system: ActorSystem) -> Self {
+    Self { inner: Default::default(), system }
+  }
+
+  /// System tracking the instances created by this injector.
+  pub fn system(&self) -> &ActorSystem {
+    &self.system
+  }
+
   pub async fn bind<T, I>(&self)
     where
       T: ?Sized + Send + 'static,
@@ -104,6 +155,92 @@ impl Injector {
     inner.injected_instances.insert(Binding::Unnamed(TypeId::of::<C>()), lazy_cell);
   }
 
+  /// Stops the instances created by this injector, each one after all instances injected with it.
+  /// Waits for every instance to terminate, so stop hooks run before its dependencies stop.
+  /// Fails if the remaining instances depend on each other, leaving them running and bound.
+  /// Stopped instances are created anew when requested after the shutdown.
+  pub async fn shutdown(&self) -> Result<(), ShutdownError> {
+    let (mut created, injected, dependencies) = {
+      let mut inner = self.inner.write().await;
+      let created = std::mem::take(&mut inner.created_instances);
+      let injected = created.keys()
+        .filter_map(|binding| inner.injected_instances.remove_entry(binding))
+        .collect::<HashMap<_, _>>();
+
+      (created, injected, std::mem::take(&mut inner.dependencies))
+    };
+
+    let mut dependents = HashMap::<&Binding, usize>::new();
+    for dependency in dependencies.values().flatten() {
+      *dependents.entry(dependency).or_default() += 1;
+    }
+
+    while !created.is_empty() {
+      let ready = created.keys()
+        .filter(|binding| dependents.get(binding).copied().unwrap_or(0) == 0)
+        .cloned()
+        .collect::<Vec<_>>();
+
+      if ready.is_empty() {
+        let mut names = created.values().map(|instance| instance.name).collect::<Vec<_>>();
+        names.sort_unstable();
+        self.restore(created, injected, dependencies).await;
+        return Err(ShutdownError::Cycle(names));
+      }
+
+      let stopping = ready.iter()
+        .filter_map(|binding| created.remove(binding))
+        .map(|instance| (instance.stop)());
+      futures::future::join_all(stopping).await;
+
+      for dependency in ready.iter().filter_map(|binding| dependencies.get(binding)).flatten() {
+        if let Some(count) = dependents.get_mut(dependency) {
+          *count -= 1;
+        }
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Puts back the bindings of instances a failed [`Self::shutdown`] left running.
+  async fn restore(
+    &self,
+    created: HashMap<Binding, CreatedInstance>,
+    mut injected: HashMap<Binding, Arc<dyn Any + Send + Sync>>,
+    mut dependencies: HashMap<Binding, HashSet<Binding>>,
+  ) {
+    let mut inner = self.inner.write().await;
+
+    for (binding, instance) in created {
+      if let Some(instance) = injected.remove(&binding) {
+        inner.injected_instances.entry(binding.clone()).or_insert(instance);
+      }
+      if let Some(dependencies) = dependencies.remove(&binding) {
+        inner.dependencies.entry(binding.clone()).or_default().extend(dependencies);
+      }
+      inner.created_instances.entry(binding).or_insert(instance);
+    }
+  }
+
+  /// Forgets the created `instance` of `binding` after it terminated, for example because the
+  /// system shut down. Returns whether the binding has to be resolved again.
+  async fn forget_terminated(&self, binding: &Binding, instance: &Arc<dyn Any + Send + Sync>) -> bool {
+    let mut inner = self.inner.write().await;
+
+    match inner.injected_instances.get(binding) {
+      Some(current) if Arc::ptr_eq(current, instance) => {
+        let created = inner.created_instances.remove(binding).is_some();
+        if created {
+          inner.injected_instances.remove(binding);
+          inner.dependencies.remove(binding);
+        }
+        created
+      }
+      _ => true,
+    }
+  }
+
   fn get_internal<'a, C>(&'a self, binding: Binding) -> Pin<Box<dyn Future<Output=C::HandleWrapper> + Send + Sync + 'a>>
     where
       C: HasHandleWrapper + ?Sized + Send + Sync + 'static,
@@ -126,7 +263,7 @@ impl Injector {
         return self.get_internal::<C>(binding).await;
       }
 
-      match inner_guard.injected_instances.get(&binding) {
+      match inner_guard.injected_instances.get(&binding).cloned() {
         None => {
           let mut inner_guard = RwLockUpgradableReadGuard::upgrade(inner_guard).await;
           if inner_guard.loading_injected_instances.insert(binding.clone(), type_name::<C>().to_string()).is_some() {
@@ -134,11 +271,21 @@ impl Injector {
           }
           let new_injected_instance: Arc<LazyCell<C::HandleWrapper>> = Arc::new(LazyCell::new({
             let injector = self.clone();
+            let binding = binding.clone();
             async move {
-              C::HandleWrapper::create_instance(injector.clone()).await.as_ref().clone()
+              CREATING.scope(binding, C::HandleWrapper::create_instance(injector.clone())).await.as_ref().clone()
             }
           }));
 
+          let stop_instance = new_injected_instance.clone();
+          inner_guard.created_instances.insert(binding.clone(), CreatedInstance {
+            name: type_name::<C>(),
+            stop: Box::new(move || {
+              let instance = stop_instance.clone();
+              Box::pin(async move { instance.get().await.stop_instance().await })
+            }),
+          });
+          inner_guard.record_dependency(&binding);
           inner_guard.injected_instances.insert(binding.clone(), new_injected_instance.clone());
           drop(inner_guard);
           let new_injected_instance = new_injected_instance.get().await.clone();
@@ -150,9 +297,96 @@ impl Injector {
           if self.inner.read().await.loading_injected_instances.contains_key(&binding) {
             panic!("detected circular reference. {:?}", &inner_guard.loading_injected_instances.values());
           }
-          injected_instance.clone().deref().downcast_ref::<LazyCell<C::HandleWrapper>>().unwrap().get().await.clone()
+          if CREATING.try_with(|_| ()).is_ok() {
+            RwLockUpgradableReadGuard::upgrade(inner_guard).await.record_dependency(&binding);
+          } else {
+            drop(inner_guard);
+          }
+          let instance = injected_instance.clone().deref().downcast_ref::<LazyCell<C::HandleWrapper>>().unwrap().get().await.clone();
+          if !instance.is_terminated() || !self.forget_terminated(&binding, &injected_instance).await {
+            return instance;
+          }
+          self.get_internal::<C>(binding).await
         }
       }
     })
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::atomic::{AtomicUsize, Ordering};
+
+  #[derive(Component, Injectable)]
+  pub struct Database {
+    #[inject_default] connected: bool,
+  }
+
+  #[actor]
+  impl Database {
+    pub async fn connect(&mut self) {
+      self.connected = true;
+    }
+  }
+
+  #[tokio::test]
+  async fn created_instances_are_tracked_by_the_system() {
+    let injector = Injector::default();
+    let _database: DatabaseHandle = injector.get::<Database>().await;
+
+    assert_eq!(injector.system().len(), 1);
+    assert_eq!(injector.system().shutdown().await.stopped.len(), 1);
+  }
+
+  #[tokio::test]
+  async fn instances_stopped_by_the_system_are_created_anew() {
+    let injector = Injector::default();
+    let stopped: DatabaseHandle = injector.get::<Database>().await;
+    injector.system().shutdown().await;
+
+    let database: DatabaseHandle = injector.get::<Database>().await;
+    assert_ne!(database.inner.id(), stopped.inner.id());
+    assert_eq!(database.try_connect().await, Ok(()));
+    assert_eq!(injector.system().len(), 1);
+  }
+
+  fn created(name: &'static str, stopped: &Arc<AtomicUsize>) -> CreatedInstance {
+    let stopped = stopped.clone();
+    CreatedInstance {
+      name,
+      stop: Box::new(move || {
+        stopped.fetch_add(1, Ordering::SeqCst);
+        Box::pin(async {})
+      }),
+    }
+  }
+
+  #[tokio::test]
+  async fn failed_shutdown_keeps_the_cycle_bound() {
+    let injector = Injector::default();
+    let stopped = Arc::new(AtomicUsize::new(0));
+    let binding = |name: &str| Binding::Named(TypeId::of::<()>(), name.to_string());
+
+    {
+      let mut inner = injector.inner.write().await;
+      for name in ["a", "b", "c"] {
+        inner.created_instances.insert(binding(name), created(name, &stopped));
+        inner.injected_instances.insert(binding(name), Arc::new(()));
+      }
+      inner.dependencies.insert(binding("a"), HashSet::from([binding("b")]));
+      inner.dependencies.insert(binding("b"), HashSet::from([binding("a")]));
+    }
+
+    assert_eq!(injector.shutdown().await, Err(ShutdownError::Cycle(vec!["a", "b"])));
+    assert_eq!(stopped.load(Ordering::SeqCst), 1);
+
+    let inner = injector.inner.read().await;
+    for name in ["a", "b"] {
+      assert!(inner.created_instances.contains_key(&binding(name)));
+      assert!(inner.injected_instances.contains_key(&binding(name)));
+    }
+    assert!(!inner.injected_instances.contains_key(&binding("c")));
+    assert_eq!(inner.dependencies.get(&binding("a")), Some(&HashSet::from([binding("b")])));
+  }
+}
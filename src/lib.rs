@@ -1,6 +1,11 @@
 #![feature(unsize)]
 #![feature(arbitrary_self_types)]
 #![feature(type_alias_impl_trait)]
+#![feature(associated_type_defaults)]
+extern crate self as async_actor;
+
 pub mod system;
 pub mod util;
 pub mod inject;
+pub mod event_bus;
+pub mod registry;
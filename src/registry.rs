@@ -0,0 +1,212 @@
+use std::any::{Any, TypeId};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fmt::{Debug, Display, Formatter};
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use crate::system::{ActorId, Component, ComponentHandle, ComponentHandleWrapper, WeakComponentHandle};
+
+type Key<K> = (K, TypeId);
+
+struct Registration {
+  id: ActorId,
+  handle: Box<dyn Any + Send + Sync>,
+}
+
+/// Running components found by key and handle type. Clones share their entries.
+///
+/// The registry holds components weakly, an entry is removed once its component terminates.
+pub struct Registry<K = String> {
+  entries: Arc<Mutex<HashMap<Key<K>, Registration>>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlreadyRegistered;
+
+impl Display for AlreadyRegistered {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    f.write_str("key is taken by a running component")
+  }
+}
+
+impl std::error::Error for AlreadyRegistered {}
+
+impl<K> Registry<K>
+  where
+    K: Eq + Hash + Clone + Send + 'static,
+{
+  pub fn new() -> Self {
+    Self { entries: Default::default() }
+  }
+
+  /// Registers `handle` under `key`. Fails if a running component with the same handle type holds the key.
+  pub fn register<H>(&self, key: impl Into<K>, handle: &H) -> Result<(), AlreadyRegistered>
+    where
+      H: ComponentHandleWrapper,
+  {
+    let key = (key.into(), TypeId::of::<H>());
+    let handle = handle.component_handle().downgrade();
+    let id = handle.id();
+
+    match self.entries.lock().unwrap().entry(key.clone()) {
+      Entry::Occupied(entry) if Self::upgrade::<H>(entry.get()).is_some() => return Err(AlreadyRegistered),
+      Entry::Occupied(mut entry) => {
+        entry.insert(Registration { id, handle: Box::new(handle.clone()) });
+      }
+      Entry::Vacant(entry) => {
+        entry.insert(Registration { id, handle: Box::new(handle.clone()) });
+      }
+    }
+
+    let entries = Arc::downgrade(&self.entries);
+    tokio::spawn(async move {
+      handle.terminated().await;
+
+      if let Some(entries) = entries.upgrade() {
+        let mut entries = entries.lock().unwrap();
+
+        if entries.get(&key).map(|registration| registration.id == id).unwrap_or(false) {
+          entries.remove(&key);
+        }
+      }
+    });
+
+    Ok(())
+  }
+
+  pub fn lookup<H>(&self, key: impl Into<K>) -> Option<H>
+    where
+      H: ComponentHandleWrapper,
+  {
+    let entries = self.entries.lock().unwrap();
+    let registration = entries.get(&(key.into(), TypeId::of::<H>()))?;
+
+    Self::upgrade::<H>(registration).map(H::Component::create_wrapper)
+  }
+
+  /// Removes the entry without stopping its component. Returns whether there was one.
+  pub fn unregister<H>(&self, key: impl Into<K>) -> bool
+    where
+      H: ComponentHandleWrapper,
+  {
+    self.entries.lock().unwrap().remove(&(key.into(), TypeId::of::<H>())).is_some()
+  }
+
+  fn upgrade<H>(registration: &Registration) -> Option<ComponentHandle<H::Component>>
+    where
+      H: ComponentHandleWrapper,
+  {
+    registration.handle.downcast_ref::<WeakComponentHandle<H::Component>>()?.upgrade()
+  }
+}
+
+impl<K> Clone for Registry<K> {
+  fn clone(&self) -> Self {
+    Self { entries: self.entries.clone() }
+  }
+}
+
+impl<K> Default for Registry<K>
+  where
+    K: Eq + Hash + Clone + Send + 'static,
+{
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl<K> Debug for Registry<K> {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("Registry")
+      .field("len", &self.entries.lock().unwrap().len())
+      .finish()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::time::Duration;
+  use async_actor_proc::{actor, Component};
+  use crate as async_actor;
+
+  #[derive(Component)]
+  pub struct Greeter {
+    greeting: &'static str,
+  }
+
+  #[actor]
+  impl Greeter {
+    pub async fn greet(&mut self) -> &'static str {
+      self.greeting
+    }
+  }
+
+  #[derive(Component)]
+  pub struct Listener;
+
+  #[actor]
+  impl Listener {
+    pub async fn listen(&mut self) {}
+  }
+
+  #[tokio::test]
+  async fn registered_components_are_found_by_key_and_type() {
+    let registry = Registry::<String>::new();
+    let greeter = Greeter { greeting: "hello" }.start();
+    let listener = Listener.start();
+
+    assert_eq!(registry.register("main", &greeter), Ok(()));
+    assert_eq!(registry.register("main", &listener), Ok(()));
+
+    let Some(found) = registry.lookup::<GreeterHandle>("main") else { panic!("greeter is not registered") };
+    assert_eq!(found.try_greet().await, Ok("hello"));
+    assert!(registry.lookup::<ListenerHandle>("main").is_some());
+    assert!(registry.lookup::<GreeterHandle>("other").is_none());
+  }
+
+  #[tokio::test]
+  async fn running_components_keep_their_key() {
+    let registry = Registry::<String>::new();
+    let first = Greeter { greeting: "first" }.start();
+    let second = Greeter { greeting: "second" }.start();
+    registry.register("main", &first).unwrap();
+
+    assert_eq!(registry.register("main", &second), Err(AlreadyRegistered));
+
+    first.stop();
+    first.terminated().await;
+    assert_eq!(registry.register("main", &second), Ok(()));
+    let Some(found) = registry.lookup::<GreeterHandle>("main") else { panic!("greeter is not registered") };
+    assert_eq!(found.try_greet().await, Ok("second"));
+  }
+
+  #[tokio::test]
+  async fn unregistered_components_keep_running() {
+    let registry = Registry::<String>::new();
+    let greeter = Greeter { greeting: "hello" }.start();
+    registry.register("main", &greeter).unwrap();
+
+    assert!(registry.unregister::<GreeterHandle>("main"));
+    assert!(!registry.unregister::<GreeterHandle>("main"));
+    assert!(registry.lookup::<GreeterHandle>("main").is_none());
+    assert_eq!(greeter.try_greet().await, Ok("hello"));
+  }
+
+  #[tokio::test]
+  async fn entries_are_removed_once_their_component_terminates() {
+    let registry = Registry::<String>::new();
+    let greeter = Greeter { greeting: "hello" }.start();
+    registry.register("main", &greeter).unwrap();
+
+    greeter.stop();
+    greeter.terminated().await;
+
+    tokio::time::timeout(Duration::from_secs(1), async {
+      while !registry.entries.lock().unwrap().is_empty() {
+        tokio::task::yield_now().await;
+      }
+    }).await.unwrap();
+    assert!(registry.lookup::<GreeterHandle>("main").is_none());
+  }
+}
@@ -1,11 +1,39 @@
 use crate::util::container::Container;
-use crate::util::resolvable::{AsyncResolvable, Resolver, SyncResolvable};
+use crate::util::resolvable::{AsyncResolvable, Resolver, SyncResolvable, ThinResolver};
+use futures::stream::FuturesUnordered;
+use futures::{FutureExt, StreamExt};
+use std::any::{type_name, Any, TypeId};
+use std::collections::HashMap;
+use std::fmt::{Debug, Display, Formatter};
 use std::future::Future;
+use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
+use std::panic::AssertUnwindSafe;
 use std::ops::Deref;
 use std::pin::Pin;
 use std::sync::Arc;
+use std::task::Poll;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
-use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use crate::util::mailbox::{self, MailboxItem, MailboxReceiver, MailboxSender, MailboxWeakSender, TrySendError};
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::task::{AbortHandle, JoinHandle, JoinSet, LocalSet};
+use tokio::time::Instant;
+
+pub use crate::util::cancellation::CancellationToken;
+pub use crate::util::mailbox::{OverflowPolicy, Priority};
+pub use futures::Stream;
+
+tokio::task_local! {
+  static CANCELLATION: CancellationToken;
+  static CALL_CHAIN: Vec<ActorId>;
+}
+
+/// Token of the message currently handled by this task. It is cancelled once the caller stops
+/// waiting for the answer. Outside of a handler the token is never cancelled.
+pub fn cancellation() -> CancellationToken {
+  CANCELLATION.try_with(CancellationToken::clone).unwrap_or_default()
+}
 
 #[repr(transparent)]
 pub struct SendVoidPtr(pub *mut std::ffi::c_void);
@@ -16,394 +44,2229 @@ pub trait HasHandleWrapper {
   type HandleWrapper: Clone + Send + Sync + 'static;
 }
 
-#[async_trait::async_trait]
-pub trait Component: HasHandleWrapper + Sized + Send + 'static {
-  fn create_wrapper(handle: ComponentHandle<Self>) -> Self::HandleWrapper;
+/// Handle type generated for a component, gives access to the handle it wraps.
+pub trait ComponentHandleWrapper: Clone + Send + Sync + 'static {
+  type Component: Component<HandleWrapper=Self>;
 
-  fn start(self) -> Self::HandleWrapper {
-    let (receiver, handle) = ComponentHandle::create();
-    let wrapper = Self::create_wrapper(handle);
+  fn component_handle(&self) -> &ComponentHandle<Self::Component>;
+}
+
+pub trait Component: HasHandleWrapper + Sized + 'static {
+  /// [`Threaded`], or [`Local`] when derived with `#[component(local)]`, which runs on a `LocalSet`
+  /// and need not be `Send`.
+  type Mode: ExecutionMode<Self>;
+  type Runner: ComponentRunner<Self> = DefaultComponentRunner<Self>;
+  const PANIC_POLICY: PanicPolicy = PanicPolicy::Resume;
+  /// Maximum number of queued messages, at least 1, or `None` for an unbounded mailbox.
+  const MAILBOX_CAPACITY: Option<usize> = None;
+  /// Applied when a message is dispatched to a full mailbox. Messages dropped by the policy are
+  /// answered with [`DispatchError::Dropped`].
+  const OVERFLOW_POLICY: OverflowPolicy = OverflowPolicy::Block;
 
-    let runner = DefaultComponentRunner::run(self, receiver);
+  fn create_wrapper(handle: ComponentHandle<Self>) -> Self::HandleWrapper;
 
+  /// Spawns the component without tracking it, see [`ActorSystem::spawn`].
+  fn start(self) -> Self::HandleWrapper
+    where
+      Self: Component<Mode=Threaded> + Send,
+  {
+    let (handle, runner) = prepare(self);
     tokio::spawn(runner);
 
-    wrapper
+    Self::create_wrapper(handle)
   }
-}
 
-type PinnedFuture<'a> = Pin<Box<dyn Future<Output=()> + Send + 'a>>;
-type ComponentMessageDispatchFn<C> =
-fn(&mut C, SendVoidPtr) -> PinnedFuture<'_>;
+  /// Spawns the component on `local_set` without tracking it, see [`ActorSystem::spawn_local`].
+  /// It runs once the `LocalSet` is driven and never leaves its thread, while its handles are `Send`.
+  fn start_local(self, local_set: &LocalSet) -> Self::HandleWrapper
+    where
+      Self: Component<Mode=Local>,
+  {
+    let (handle, runner) = prepare(self);
+    local_set.spawn_local(runner);
 
-#[async_trait::async_trait]
-pub trait ComponentMessageHandler<R>
-  where
-    Self: 'static + Component,
-    R: 'static + Send,
-{
-  type Answer: 'static + Send;
+    Self::create_wrapper(handle)
+  }
 
-  fn dispatch(&mut self, payload: SendVoidPtr) -> PinnedFuture {
-    let resolver =
-      unsafe { Container::<Resolver<R, Self::Answer>>::from_raw(payload.0) }.into_inner();
+  fn started(&mut self) -> ModeFuture<'_, Self, ()> {
+    Self::Mode::ready()
+  }
 
-    Box::pin(async move {
-      let (resolver, meta) = resolver.split();
-      let answer = self.handle(meta).await;
-      resolver.resolve(answer);
-    })
+  fn stopping(&mut self) -> ModeFuture<'_, Self, ()> {
+    Self::Mode::ready()
   }
 
-  async fn handle(&mut self, request: R) -> Self::Answer;
+  fn stopped(self) -> ModeFuture<'static, Self, ()> {
+    Self::Mode::ready()
+  }
 }
 
-struct AnyComponentMessage<C>
+/// Future type of a component's handlers, hooks and runner, `Send` unless it is [`Local`].
+pub type ModeFuture<'a, C, T> = <<C as Component>::Mode as ExecutionMode<C>>::Future<'a, T>;
+
+/// Where a component runs, chosen with [`Component::Mode`]. Implemented by [`Threaded`] and [`Local`].
+pub trait ExecutionMode<C>: Sized + 'static
   where
     C: Component,
 {
-  payload: *mut std::ffi::c_void,
-  dispatcher: ComponentMessageDispatchFn<C>,
-}
+  type Future<'a, T: 'a>: Future<Output=T> + 'a;
 
-unsafe impl<C> Send for AnyComponentMessage<C> where C: Component {}
+  #[doc(hidden)]
+  fn ready<'a>() -> Self::Future<'a, ()>;
 
-#[derive(Debug)]
-pub struct ComponentHandle<C>
-  where
-    C: Component,
-{
-  sender: UnboundedSender<AnyComponentMessage<C>>,
+  #[doc(hidden)]
+  fn resolve<'a, A>(resolver: ThinResolver<Result<A, DispatchError>>, handler: Self::Future<'a, A>) -> Self::Future<'a, Result<(), DispatchError>>
+    where
+      A: Send + 'static;
+
+  #[doc(hidden)]
+  fn run_default<'a>(component: &'a mut C, mailbox: &'a mut ComponentMailbox<C>) -> Self::Future<'a, ()>;
 }
 
-impl<C> ComponentHandle<C>
+/// Components spawned with `tokio::spawn`, which may move between worker threads.
+#[derive(Debug, Clone, Copy)]
+pub struct Threaded;
+
+impl<C> ExecutionMode<C> for Threaded
   where
-    C: Component,
+    C: Component<Mode=Threaded> + Send,
 {
-  fn new(sender: UnboundedSender<AnyComponentMessage<C>>) -> Self {
-    Self { sender }
-  }
-
-  fn create() -> (UnboundedReceiver<AnyComponentMessage<C>>, Self) {
-    let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+  type Future<'a, T: 'a> = Pin<Box<dyn Future<Output=T> + Send + 'a>>;
 
-    (receiver, Self::new(sender))
+  fn ready<'a>() -> Self::Future<'a, ()> {
+    Box::pin(std::future::ready(()))
   }
 
-  pub async fn dispatch<M>(&self, message: M) -> <C as ComponentMessageHandler<M>>::Answer
+  fn resolve<'a, A>(resolver: ThinResolver<Result<A, DispatchError>>, handler: Self::Future<'a, A>) -> Self::Future<'a, Result<(), DispatchError>>
     where
-      C: ComponentMessageHandler<M>,
-      M: Send + 'static,
+      A: Send + 'static,
   {
-    DispatcherImpl::dispatch_async(&self.sender, message).await
+    Box::pin(resolve_answer(resolver, handler))
   }
 
-  pub fn dispatch_sync_nowait<M>(&self, message: M)
-    where
-      C: ComponentMessageHandler<M>,
-      M: Send + 'static,
-  {
-    DispatcherImpl::dispatch_sync_nowait(&self.sender, message)
+  fn run_default<'a>(component: &'a mut C, mailbox: &'a mut ComponentMailbox<C>) -> Self::Future<'a, ()> {
+    Box::pin(DefaultComponentRunner::run_messages(component, mailbox))
   }
+}
 
-  pub fn dispatch_sync<M>(&self, message: M) -> C::Answer
-    where
-      C: ComponentMessageHandler<M>,
-      M: Send + 'static,
-  {
-    DispatcherImpl::dispatch_sync(&self.sender, message)
+/// Modes whose components can run `#[actor(concurrent_reads)]` readers. Readers of a [`Threaded`]
+/// component share it across worker threads, so it has to be `Sync`; [`Local`] ones never leave
+/// their thread.
+#[diagnostic::on_unimplemented(
+  message = "`#[actor(concurrent_reads)]` requires `{C}` to be `Sync`",
+  label = "readers of threaded components share them across threads",
+)]
+pub trait ConcurrentReads<C> {}
+
+impl<C> ConcurrentReads<C> for Threaded where C: Sync {}
+
+impl<C> ConcurrentReads<C> for Local {}
+
+/// Components spawned on a `LocalSet`, which stay on its thread and need not be `Send`. Start
+/// them with [`Component::start_local`] or [`ActorSystem::spawn_local`]. Their handles are `Send`,
+/// but they cannot be taken back with `stop_and_take` or created by the injector.
+#[derive(Debug, Clone, Copy)]
+pub struct Local;
+
+impl<C> ExecutionMode<C> for Local
+  where
+    C: Component<Mode=Local>,
+{
+  type Future<'a, T: 'a> = Pin<Box<dyn Future<Output=T> + 'a>>;
+
+  fn ready<'a>() -> Self::Future<'a, ()> {
+    Box::pin(std::future::ready(()))
   }
 
-  pub fn make_sender<M>(&self) -> MessageSender<M, <C as ComponentMessageHandler<M>>::Answer>
+  fn resolve<'a, A>(resolver: ThinResolver<Result<A, DispatchError>>, handler: Self::Future<'a, A>) -> Self::Future<'a, Result<(), DispatchError>>
     where
-      C: ComponentMessageHandler<M>,
-      M: Send + 'static,
+      A: Send + 'static,
   {
-    MessageSender::<M, C::Answer>::create(self.sender.clone())
+    Box::pin(resolve_answer(resolver, handler))
   }
 
-  pub fn make_transforming_sender<M, N, T>(
-    &self,
-    transformer: T,
-  ) -> MessageSender<M, <C as ComponentMessageHandler<N>>::Answer>
-    where
-      C: ComponentMessageHandler<N>,
-      M: Send + 'static,
-      N: Send + 'static,
-      T: Fn(M) -> N + Send + Sync + 'static,
-  {
-    MessageSender::<M, C::Answer>::create_transforming(self.sender.clone(), transformer)
+  fn run_default<'a>(component: &'a mut C, mailbox: &'a mut ComponentMailbox<C>) -> Self::Future<'a, ()> {
+    Box::pin(DefaultComponentRunner::run_messages(component, mailbox))
   }
 }
 
-impl<C> Clone for ComponentHandle<C>
+/// Hooks of components derived with `#[component(lifecycle)]`, called by [`Component::started`],
+/// [`Component::stopping`] and [`Component::stopped`]. Named apart from those so that calls on
+/// `self` are not ambiguous. The derive checks that the hooks are `Send` for [`Threaded`] components.
+#[allow(async_fn_in_trait)]
+pub trait ComponentLifecycle: Component {
+  async fn on_started(&mut self) {}
+
+  async fn on_stopping(&mut self) {}
+
+  async fn on_stopped(self) {}
+}
+
+/// Creates the handle of `component` and the future running it until it terminates.
+fn prepare<C>(component: C) -> (ComponentHandle<C>, impl Future<Output=()> + 'static)
   where
     C: Component,
 {
-  fn clone(&self) -> Self {
-    let sender = self.sender.clone();
+  let (receiver, handle) = ComponentHandle::create();
+  // Subscribed before the handle is returned, so signals sent before the runner is first polled are not missed
+  let mailbox = ComponentMailbox {
+    receiver,
+    signal: handle.control.signal.subscribe(),
+    control: handle.control.clone(),
+    context: Context::new(handle.downgrade()),
+    state: MailboxState::Running,
+  };
+
+  (handle, run_component(component, mailbox))
+}
 
-    Self { sender }
-  }
+const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Spawns components and keeps track of them until they terminate, so they can be stopped
+/// together with [`ActorSystem::shutdown`]. Clones share the tracked components.
+#[derive(Clone)]
+pub struct ActorSystem {
+  inner: Arc<std::sync::Mutex<ActorSystemInner>>,
+  shutdown_timeout: Duration,
 }
 
-impl<C> From<ComponentHandle<C>> for ComponentHandleUnique<C> where C: Component {
-  fn from(value: ComponentHandle<C>) -> Self {
-    Self {
-      sender: value.sender.clone()
+#[derive(Default)]
+struct ActorSystemInner {
+  tasks: JoinSet<()>,
+  actors: HashMap<tokio::task::Id, TrackedActor>,
+  /// Number of [`ActorSystem::shutdown`] calls in progress.
+  shutting_down: usize,
+}
+
+impl ActorSystemInner {
+  /// Forgets the components that terminated on their own.
+  fn reap(&mut self) {
+    while let Some(finished) = self.tasks.try_join_next_with_id() {
+      let id = finished.map(|(id, _)| id).unwrap_or_else(|error| error.id());
+      self.actors.remove(&id);
     }
   }
 }
 
-#[derive(Debug)]
-pub struct ComponentHandleUnique<C>
-  where
-    C: Component,
-{
-  sender: UnboundedSender<AnyComponentMessage<C>>,
+/// Ends a shutdown even if [`ActorSystem::shutdown`] is not polled to completion.
+struct ShuttingDown<'a>(&'a std::sync::Mutex<ActorSystemInner>);
+
+impl<'a> ShuttingDown<'a> {
+  fn begin(inner: &'a std::sync::Mutex<ActorSystemInner>) -> Self {
+    inner.lock().unwrap().shutting_down += 1;
+    Self(inner)
+  }
 }
 
-impl<C> ComponentHandleUnique<C>
-  where
-    C: Component,
-{
-  fn new(sender: UnboundedSender<AnyComponentMessage<C>>) -> Self {
-    Self { sender }
+impl Drop for ShuttingDown<'_> {
+  fn drop(&mut self) {
+    self.0.lock().unwrap().shutting_down -= 1;
   }
+}
 
-  fn create() -> (UnboundedReceiver<AnyComponentMessage<C>>, Self) {
-    let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+struct TrackedActor {
+  info: ActorInfo,
+  stop: Box<dyn Fn() + Send>,
+}
 
-    (receiver, Self::new(sender))
-  }
+/// Component tracked by an [`ActorSystem`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActorInfo {
+  pub id: ActorId,
+  /// Type name of the component.
+  pub name: &'static str,
+}
 
-  pub async fn dispatch<M>(&self, message: M) -> <C as ComponentMessageHandler<M>>::Answer
-    where
-      C: ComponentMessageHandler<M>,
-      M: Send + 'static,
-  {
-    DispatcherImpl::dispatch_async(&self.sender, message).await
+/// Outcome of [`ActorSystem::shutdown`].
+#[derive(Debug, Clone, Default)]
+pub struct ShutdownReport {
+  /// Components that finished their queued messages and stopped in time.
+  pub stopped: Vec<ActorInfo>,
+  /// Components that were still busy when the timeout elapsed and got aborted.
+  pub aborted: Vec<ActorInfo>,
+}
+
+impl ShutdownReport {
+  pub fn is_clean(&self) -> bool {
+    self.aborted.is_empty()
   }
+}
 
-  pub fn dispatch_sync_nowait<M>(&self, message: M)
-    where
-      C: ComponentMessageHandler<M>,
-      M: Send + 'static,
-  {
-    DispatcherImpl::dispatch_sync_nowait(&self.sender, message)
+impl ActorSystem {
+  pub fn new() -> Self {
+    Self {
+      inner: Default::default(),
+      shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+    }
   }
 
-  pub fn dispatch_sync<M>(&self, message: M) -> C::Answer
-    where
-      C: ComponentMessageHandler<M>,
-      M: Send + 'static,
-  {
-    DispatcherImpl::dispatch_sync(&self.sender, message)
+  /// Time all components get to stop before [`ActorSystem::shutdown`] aborts them, 10 seconds by default.
+  pub fn with_shutdown_timeout(mut self, timeout: Duration) -> Self {
+    self.shutdown_timeout = timeout;
+    self
   }
 
-  pub fn make_sender<M>(&self) -> MessageSender<M, <C as ComponentMessageHandler<M>>::Answer>
+  /// Starts `component` like [`Component::start`] and tracks it until it terminates. Components
+  /// spawned while [`ActorSystem::shutdown`] is in progress are stopped right away, once it
+  /// returned the system runs new components again.
+  pub fn spawn<C>(&self, component: C) -> C::HandleWrapper
     where
-      C: ComponentMessageHandler<M>,
-      M: Send + 'static,
+      C: Component<Mode=Threaded> + Send,
   {
-    MessageSender::<M, C::Answer>::create(self.sender.clone())
+    let (handle, runner) = prepare(component);
+    self.track(handle, |tasks| tasks.spawn(runner))
   }
 
-  pub fn make_transforming_sender<M, N, T>(
-    &self,
-    transformer: T,
-  ) -> MessageSender<M, <C as ComponentMessageHandler<N>>::Answer>
+  /// Starts the [`Local`] `component` on `local_set` like [`Component::start_local`] and tracks it.
+  /// [`ActorSystem::shutdown`] only finishes once the `LocalSet` is driven.
+  pub fn spawn_local<C>(&self, component: C, local_set: &LocalSet) -> C::HandleWrapper
     where
-      C: ComponentMessageHandler<N>,
-      M: Send + 'static,
-      N: Send + 'static,
-      T: Fn(M) -> N + Send + Sync + 'static,
+      C: Component<Mode=Local>,
   {
-    MessageSender::<M, C::Answer>::create_transforming(self.sender.clone(), transformer)
+    let (handle, runner) = prepare(component);
+    self.track(handle, |tasks| tasks.spawn_local_on(runner, local_set))
   }
-}
-
-
-type FutureMessageDispatcher<M, R> =
-Arc<dyn Fn(M) -> Pin<Box<dyn Future<Output=R> + Send>> + Send + Sync>;
-type SyncNowaitMessageDispatcher<M> = Arc<dyn Fn(M) + Send + Sync>;
-type SyncMessageDispatcher<M, R> = Arc<dyn Fn(M) -> R + Send + Sync>;
-
-#[derive(Clone)]
-pub struct MessageSender<M, R> {
-  async_dispatcher: FutureMessageDispatcher<M, R>,
-  sync_nowait_dispatcher: SyncNowaitMessageDispatcher<M>,
-  sync_dispatcher: SyncMessageDispatcher<M, R>,
-}
 
-impl<M, R> MessageSender<M, R> {
-  fn create<C>(sender: UnboundedSender<AnyComponentMessage<C>>) -> Self
+  fn track<C, F>(&self, handle: ComponentHandle<C>, spawn: F) -> C::HandleWrapper
     where
-      C: ComponentMessageHandler<M, Answer=R>,
-      M: Send + 'static,
-      R: Send,
+      C: Component,
+      F: FnOnce(&mut JoinSet<()>) -> AbortHandle,
   {
-    let fut_sender = sender.clone();
-    let async_dispatcher =
-      move |message: M| -> Pin<Box<dyn Future<Output=C::Answer> + Send>> {
-        let fut_sender = fut_sender.clone();
-
-        Box::pin(async move { DispatcherImpl::dispatch_async(&fut_sender, message).await })
-      };
+    let info = ActorInfo { id: handle.id(), name: type_name::<C>() };
+    let sender = handle.sender.downgrade();
+    let control = handle.control.clone();
+    let stop = move || {
+      sender.close();
+      control.stop();
+    };
 
-    let nowait_sender = sender.clone();
-    let sync_nowait_dispatcher =
-      move |message: M| DispatcherImpl::dispatch_sync_nowait(&nowait_sender, message);
+    let mut inner = self.inner.lock().unwrap();
+    inner.reap();
 
-    let sync_dispatcher = move |message: M| DispatcherImpl::dispatch_sync(&sender, message);
+    let task = spawn(&mut inner.tasks);
+    inner.actors.insert(task.id(), TrackedActor { info, stop: Box::new(stop) });
 
-    Self {
-      async_dispatcher: Arc::new(async_dispatcher),
-      sync_nowait_dispatcher: Arc::new(sync_nowait_dispatcher),
-      sync_dispatcher: Arc::new(sync_dispatcher),
+    if inner.shutting_down > 0 {
+      handle.stop();
     }
-  }
-
-  fn create_transforming<C, N, T>(
-    sender: UnboundedSender<AnyComponentMessage<C>>,
-    transformer: T,
-  ) -> Self
-    where
-      C: ComponentMessageHandler<N, Answer=R>,
-      M: Send + 'static,
-      N: Send + 'static,
-      R: Send,
-      T: Fn(M) -> N + Send + Sync + 'static,
-  {
-    let fut_sender = sender.clone();
-    let transformer = Arc::new(transformer);
-
-    let transformer_x = transformer.clone();
-    let async_dispatcher =
-      move |message: M| -> Pin<Box<dyn Future<Output=C::Answer> + Send>> {
-        let fut_sender = fut_sender.clone();
-        let transformer = transformer_x.clone();
-
-        let message = (*transformer)(message);
 
-        Box::pin(async move { DispatcherImpl::dispatch_async(&fut_sender, message).await })
-      };
+    C::create_wrapper(handle)
+  }
 
-    let nowait_sender = sender.clone();
+  /// Number of tracked components whose task did not finish yet.
+  pub fn len(&self) -> usize {
+    let mut inner = self.inner.lock().unwrap();
+    inner.reap();
+    inner.tasks.len()
+  }
 
-    let transformer_x = transformer.clone();
-    let sync_nowait_dispatcher = move |message: M| {
-      let transformer = transformer_x.clone();
-      let message = (*transformer)(message);
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
 
-      DispatcherImpl::dispatch_sync_nowait(&nowait_sender, message);
+  /// Stops all tracked components, letting them finish their queued messages. Components still
+  /// running after the shutdown timeout are aborted without running their stop hooks. The system
+  /// can be used again afterwards.
+  pub async fn shutdown(&self) -> ShutdownReport {
+    let _shutting_down = ShuttingDown::begin(&self.inner);
+    let (mut tasks, mut actors) = {
+      let mut inner = self.inner.lock().unwrap();
+      for actor in inner.actors.values() {
+        (actor.stop)();
+      }
+
+      (std::mem::take(&mut inner.tasks), std::mem::take(&mut inner.actors))
     };
 
-    let sync_dispatcher = move |message: M| {
-      let message = (*transformer)(message);
-
-      DispatcherImpl::dispatch_sync(&sender, message)
-    };
+    let deadline = Instant::now() + self.shutdown_timeout;
+    let mut report = ShutdownReport::default();
 
-    Self {
-      async_dispatcher: Arc::new(async_dispatcher),
-      sync_nowait_dispatcher: Arc::new(sync_nowait_dispatcher),
-      sync_dispatcher: Arc::new(sync_dispatcher),
+    while let Ok(Some(finished)) = tokio::time::timeout_at(deadline, tasks.join_next_with_id()).await {
+      let id = finished.map(|(id, _)| id).unwrap_or_else(|error| error.id());
+      if let Some(actor) = actors.remove(&id) {
+        report.stopped.push(actor.info);
+      }
     }
-  }
 
-  pub async fn dispatch(&self, message: M) -> R {
-    (self.async_dispatcher)(message).await
+    tasks.shutdown().await;
+    report.aborted = actors.into_values().map(|actor| actor.info).collect();
+    report
   }
+}
 
-  pub fn dispatch_sync_nowait(&self, message: M) {
-    (self.sync_nowait_dispatcher)(message)
+impl Default for ActorSystem {
+  fn default() -> Self {
+    Self::new()
   }
+}
 
-  pub fn dispatch_sync(&self, message: M) -> R {
-    (self.sync_dispatcher)(message)
+impl Debug for ActorSystem {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("ActorSystem")
+      .field("len", &self.len())
+      .field("shutdown_timeout", &self.shutdown_timeout)
+      .finish()
   }
 }
 
-struct DispatcherImpl;
+type PinnedFuture<'a> = Pin<Box<dyn Future<Output=Result<(), DispatchError>> + Send + 'a>>;
+type DispatchFuture<'a, C> = ModeFuture<'a, C, Result<(), DispatchError>>;
+type ComponentMessageDispatchFn<C> =
+for<'a> fn(&'a mut C, &'a mut Context<C>, SendVoidPtr) -> DispatchFuture<'a, C>;
+pub type ComponentMessageSharedDispatchFn<C> =
+fn(&C, SendVoidPtr) -> DispatchFuture<'_, C>;
+type ComponentMessageRejectFn = fn(SendVoidPtr, DispatchError);
+type ComponentMessageCancelledFn = fn(&SendVoidPtr) -> bool;
+
+/// What the runner does after a message handler panicked. The panic is always reported to the
+/// waiting caller as [`DispatchError::HandlerPanicked`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PanicPolicy {
+  /// Keep processing messages with the component as the panicking handler left it.
+  Resume,
+  /// Reject all queued messages and stop the component.
+  Stop,
+}
 
-impl DispatcherImpl {
-  async fn dispatch_async<C, M>(
-    sender: &UnboundedSender<AnyComponentMessage<C>>,
-    message: M,
-  ) -> C::Answer
-    where
-      C: ComponentMessageHandler<M>,
-      M: Send + 'static,
-  {
-    let (resolvable, resolver) = AsyncResolvable::new_with_meta(message);
-    let message = Self::make_message(resolver);
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DispatchError {
+  MailboxClosed,
+  HandlerPanicked(String),
+  Timeout,
+  Full,
+  Dropped,
+  Cancelled,
+  Deadlock,
+}
 
-    let _ = sender.send(message);
-    resolvable.await.unwrap()
+impl Display for DispatchError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      DispatchError::MailboxClosed => f.write_str("component mailbox is closed"),
+      DispatchError::HandlerPanicked(message) => write!(f, "message handler panicked: {}", message),
+      DispatchError::Timeout => f.write_str("message was not answered in time"),
+      DispatchError::Full => f.write_str("component mailbox is full"),
+      DispatchError::Dropped => f.write_str("message was dropped by the mailbox overflow policy"),
+      DispatchError::Cancelled => f.write_str("caller stopped waiting for the answer"),
+      DispatchError::Deadlock => f.write_str("component would wait for itself to answer"),
+    }
   }
+}
 
-  fn dispatch_sync_nowait<C, M>(sender: &UnboundedSender<AnyComponentMessage<C>>, message: M)
-    where
-      C: ComponentMessageHandler<M>,
-      M: Send + 'static,
-  {
-    let resolver = Resolver::<M, C::Answer>::noop(message);
-    let message = Self::make_message(resolver);
-    let _ = sender.send(message);
+impl std::error::Error for DispatchError {}
+
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+  if let Some(message) = panic.downcast_ref::<&str>() {
+    message.to_string()
+  } else if let Some(message) = panic.downcast_ref::<String>() {
+    message.clone()
+  } else {
+    "Box<dyn Any>".to_string()
   }
+}
 
-  fn dispatch_sync<C, M>(
-    sender: &UnboundedSender<AnyComponentMessage<C>>,
-    message: M,
-  ) -> C::Answer
-    where
-      C: ComponentMessageHandler<M>,
-      M: Send + 'static,
-  {
-    let (resolvable, resolver) = SyncResolvable::new_with_meta(message);
-    let message = Self::make_message(resolver);
-    let _ = sender.send(message);
+pub trait ComponentMessageHandler<R>
+  where
+    Self: 'static + Component,
+    R: 'static + Send,
+{
+  type Answer: 'static + Send;
 
-    resolvable.wait().unwrap()
-  }
+  const PRIORITY: Priority = Priority::Normal;
 
-  fn make_message<C, M, P>(payload: P) -> AnyComponentMessage<C>
-    where
-      C: ComponentMessageHandler<M>,
-      M: Send + 'static,
-  {
-    AnyComponentMessage {
-      payload: Container::new(payload).into_raw(),
-      dispatcher: |component, data| {
-        <C as ComponentMessageHandler<M>>::dispatch(component, data)
-      },
-    }
+  /// Set for messages implementing [`ComponentMessageReader`], which lets the runner handle them
+  /// concurrently with each other.
+  const SHARED_DISPATCH: Option<ComponentMessageSharedDispatchFn<Self>> = None;
+
+  fn dispatch<'a>(&'a mut self, context: &'a mut Context<Self>, payload: SendVoidPtr) -> DispatchFuture<'a, Self> {
+    let resolver = unsafe {
+      Container::<Resolver<R, Result<Self::Answer, DispatchError>>>::from_raw(payload.0)
+    }.into_inner();
+    let (resolver, meta) = resolver.split();
+
+    <Self::Mode as ExecutionMode<Self>>::resolve(resolver, self.handle(context, meta))
   }
-}
 
-pub struct DefaultComponentRunner<C>(C)
-  where
-    C: Component;
+  fn handle<'a>(&'a mut self, context: &'a mut Context<Self>, request: R) -> ModeFuture<'a, Self, Self::Answer>;
+}
 
-impl<C> DefaultComponentRunner<C>
+/// Messages that only need shared access to the component. Consecutive reader messages run
+/// concurrently, while other messages keep exclusive access. Generated by `#[actor(concurrent_reads)]`
+/// for `&self` methods, which requires [`Threaded`] components to be `Sync`, see [`ConcurrentReads`].
+pub trait ComponentMessageReader<R>: ComponentMessageHandler<R>
   where
-    C: Component,
+    R: 'static + Send,
 {
-  async fn run(
-    mut component: C,
-    mut receiver: UnboundedReceiver<AnyComponentMessage<C>>,
-  ) {
-    while let Some(message) = receiver.recv().await {
-      let dispatcher = message.dispatcher;
-      let payload = SendVoidPtr(message.payload);
+  fn dispatch_shared(&self, payload: SendVoidPtr) -> DispatchFuture<'_, Self> {
+    let resolver = unsafe {
+      Container::<Resolver<R, Result<Self::Answer, DispatchError>>>::from_raw(payload.0)
+    }.into_inner();
+    let (resolver, meta) = resolver.split();
 
-      (dispatcher)(&mut component, payload).await;
-    }
+    <Self::Mode as ExecutionMode<Self>>::resolve(resolver, self.handle_shared(meta))
   }
+
+  fn handle_shared(&self, request: R) -> ModeFuture<'_, Self, Self::Answer>;
 }
 
-pub trait EnsureNotDroppedForDuration {
+async fn resolve_answer<T, F>(mut resolver: ThinResolver<Result<T, DispatchError>>, handler: F) -> Result<(), DispatchError>
+  where
+    F: Future<Output=T>,
+{
+  let token = CancellationToken::new();
+
+  let handler = AssertUnwindSafe(CANCELLATION.scope(token.clone(), handler)).catch_unwind();
+  tokio::pin!(handler);
+
+  let answer = tokio::select! {
+    biased;
+    answer = &mut handler => answer,
+    _ = resolver.closed() => {
+      token.cancel();
+      handler.await
+    }
+  };
+
+  match answer {
+    Ok(answer) => {
+      resolver.resolve(Ok(answer));
+      Ok(())
+    }
+    Err(panic) => {
+      let error = DispatchError::HandlerPanicked(panic_message(&*panic));
+      resolver.resolve(Err(error.clone()));
+      Err(error)
+    }
+  }
+}
+
+pub struct AnyComponentMessage<C>
+  where
+    C: Component,
+{
+  payload: *mut std::ffi::c_void,
+  dispatcher: ComponentMessageDispatchFn<C>,
+  shared_dispatcher: Option<ComponentMessageSharedDispatchFn<C>>,
+  rejecter: ComponentMessageRejectFn,
+  cancelled: ComponentMessageCancelledFn,
+  kind: TypeId,
+  name: &'static str,
+  priority: Priority,
+  deadline: Option<Instant>,
+  call_chain: Vec<ActorId>,
+  sender: Option<ActorId>,
+}
+
+impl<C> AnyComponentMessage<C>
+  where
+    C: Component,
+{
+  fn dispatch<'a>(self, component: &'a mut C, context: &'a mut Context<C>) -> DispatchFuture<'a, C> {
+    context.sender = self.sender;
+    let message = self.forget();
+
+    (message.dispatcher)(component, context, SendVoidPtr(message.payload))
+  }
+
+  /// Whether the message may be handled concurrently with other shared messages, see [`ComponentMessageReader`].
+  pub fn is_shared(&self) -> bool {
+    self.shared_dispatcher.is_some()
+  }
+
+  /// Type of the message payload.
+  pub fn kind(&self) -> TypeId {
+    self.kind
+  }
+
+  /// Type name of the message payload, for logging and tracing.
+  pub fn name(&self) -> &'static str {
+    self.name
+  }
+
+  /// Component whose handler dispatched the message.
+  pub fn sender(&self) -> Option<ActorId> {
+    self.sender
+  }
+
+  pub fn deadline(&self) -> Option<Instant> {
+    self.deadline
+  }
+
+  fn dispatch_shared(self, component: &C) -> DispatchFuture<'_, C> {
+    let message = self.forget();
+
+    (message.shared_dispatcher.unwrap())(component, SendVoidPtr(message.payload))
+  }
+
+  /// Call chain of the handler answering this message, ending with the handling component.
+  fn call_chain(&self, id: ActorId) -> Vec<ActorId> {
+    let mut call_chain = self.call_chain.clone();
+    call_chain.push(id);
+    call_chain
+  }
+
+  /// Disables the rejecting drop, the payload is then owned by the caller.
+  fn forget(self) -> ManuallyDrop<Self> {
+    let mut message = ManuallyDrop::new(self);
+    drop(std::mem::take(&mut message.call_chain));
+    message
+  }
+
+  pub fn is_expired(&self) -> bool {
+    self.deadline.map(|deadline| deadline <= Instant::now()).unwrap_or(false)
+  }
+
+  pub fn is_cancelled(&self) -> bool {
+    (self.cancelled)(&SendVoidPtr(self.payload))
+  }
+
+  /// Answers the caller with `error` instead of handling the message.
+  pub fn reject(self, error: DispatchError) {
+    let message = self.forget();
+
+    (message.rejecter)(SendVoidPtr(message.payload), error)
+  }
+}
+
+impl<C> MailboxItem for AnyComponentMessage<C>
+  where
+    C: Component,
+{
+  fn kind(&self) -> TypeId {
+    self.kind
+  }
+
+  fn priority(&self) -> Priority {
+    self.priority
+  }
+}
+
+impl<C> Drop for AnyComponentMessage<C>
+  where
+    C: Component,
+{
+  fn drop(&mut self) {
+    (self.rejecter)(SendVoidPtr(self.payload), DispatchError::MailboxClosed);
+  }
+}
+
+unsafe impl<C> Send for AnyComponentMessage<C> where C: Component {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ComponentSignal {
+  Running,
+  Stop,
+  Abort,
+}
+
+/// Boxed so that the control stays `Send + Sync` for components that are not `Send`, which
+/// cannot be taken.
+enum TakeSlot<C> {
+  Empty,
+  Requested(Box<dyn FnOnce(C) + Send>),
+  Closed,
+}
+
+impl<C> Debug for TakeSlot<C> {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      TakeSlot::Empty => f.write_str("Empty"),
+      TakeSlot::Requested(_) => f.write_str("Requested"),
+      TakeSlot::Closed => f.write_str("Closed"),
+    }
+  }
+}
+
+#[derive(Debug)]
+struct ComponentControl<C> {
+  signal: watch::Sender<ComponentSignal>,
+  terminated: watch::Sender<bool>,
+  take: std::sync::Mutex<TakeSlot<C>>,
+  owned: AtomicBool,
+}
+
+impl<C> ComponentControl<C> {
+  fn new() -> Self {
+    let (signal, _) = watch::channel(ComponentSignal::Running);
+    let (terminated, _) = watch::channel(false);
+
+    Self {
+      signal,
+      terminated,
+      take: std::sync::Mutex::new(TakeSlot::Empty),
+      owned: AtomicBool::new(false),
+    }
+  }
+
+  fn stop(&self) {
+    self.signal.send_if_modified(|signal| {
+      if *signal == ComponentSignal::Running {
+        *signal = ComponentSignal::Stop;
+        true
+      } else {
+        false
+      }
+    });
+  }
+
+  fn abort(&self) {
+    self.signal.send_replace(ComponentSignal::Abort);
+  }
+
+  async fn aborted(&self) {
+    let mut signal = self.signal.subscribe();
+
+    while *signal.borrow_and_update() != ComponentSignal::Abort {
+      let _ = signal.changed().await;
+    }
+  }
+
+  async fn terminated(&self) {
+    let mut terminated = self.terminated.subscribe();
+
+    while !*terminated.borrow_and_update() {
+      let _ = terminated.changed().await;
+    }
+  }
+
+  async fn stop_and_take(&self) -> Option<C>
+    where
+      C: Send + 'static,
+  {
+    let (sender, receiver) = oneshot::channel();
+
+    {
+      let mut take = self.take.lock().unwrap();
+      if let TakeSlot::Closed = *take {
+        return None;
+      }
+      *take = TakeSlot::Requested(Box::new(move |component| drop(sender.send(component))));
+    }
+
+    self.stop();
+    receiver.await.ok()
+  }
+
+  fn close_take(&self) -> Option<Box<dyn FnOnce(C) + Send>> {
+    match std::mem::replace(&mut *self.take.lock().unwrap(), TakeSlot::Closed) {
+      TakeSlot::Requested(sender) => Some(sender),
+      _ => None,
+    }
+  }
+}
+
+struct TerminationGuard<'a, C>(&'a ComponentControl<C>);
+
+impl<C> Drop for TerminationGuard<'_, C> {
+  fn drop(&mut self) {
+    self.0.close_take();
+    self.0.terminated.send_replace(true);
+  }
+}
+
+#[derive(Debug)]
+pub struct ComponentHandle<C>
+  where
+    C: Component,
+{
+  sender: MailboxSender<AnyComponentMessage<C>>,
+  control: Arc<ComponentControl<C>>,
+  timeout: Option<Duration>,
+}
+
+impl<C> ComponentHandle<C>
+  where
+    C: Component,
+{
+  fn new(sender: MailboxSender<AnyComponentMessage<C>>, control: Arc<ComponentControl<C>>) -> Self {
+    Self { sender, control, timeout: None }
+  }
+
+  fn create() -> (MailboxReceiver<AnyComponentMessage<C>>, Self) {
+    let (sender, receiver) = mailbox::channel(C::MAILBOX_CAPACITY, C::OVERFLOW_POLICY);
+
+    (receiver, Self::new(sender, Arc::new(ComponentControl::new())))
+  }
+
+  /// Finishes the in-flight message and all queued ones, then stops the component.
+  /// Messages dispatched after this call are rejected.
+  pub fn stop(&self) {
+    self.sender.close();
+    self.control.stop()
+  }
+
+  /// Stops the component immediately, cancelling the in-flight message and dropping all queued ones.
+  pub fn abort(&self) {
+    self.control.abort()
+  }
+
+  pub fn id(&self) -> ActorId {
+    ActorId(self.sender.id())
+  }
+
+  /// Creates a handle that does not keep the component running.
+  pub fn downgrade(&self) -> WeakComponentHandle<C> {
+    WeakComponentHandle {
+      sender: self.sender.downgrade(),
+      control: self.control.clone(),
+      timeout: self.timeout,
+    }
+  }
+
+  /// Applies `timeout` to every awaited dispatch through this handle and the senders made from it.
+  /// Messages still queued when their caller timed out are skipped.
+  pub fn with_timeout(mut self, timeout: Duration) -> Self {
+    self.timeout = Some(timeout);
+    self
+  }
+
+  /// Whether the component has finished running, see [`Self::terminated`].
+  pub fn is_terminated(&self) -> bool {
+    *self.control.terminated.borrow()
+  }
+
+  /// Resolves once the component has finished running, however it was stopped.
+  pub async fn terminated(&self) {
+    self.control.terminated().await
+  }
+
+  /// Stops the component like [`Self::stop`] and hands it back instead of calling `stopped`.
+  /// Returns `None` if the component already terminated, was taken by another caller or is owned
+  /// by a [`ComponentHandleUnique`].
+  pub async fn stop_and_take(&self) -> Option<C>
+    where
+      C: Send,
+  {
+    if self.control.owned.load(Ordering::Acquire) {
+      return None;
+    }
+    self.sender.close();
+    self.control.stop_and_take().await
+  }
+
+  pub async fn dispatch<M>(&self, message: M) -> <C as ComponentMessageHandler<M>>::Answer
+    where
+      C: ComponentMessageHandler<M>,
+      M: Send + 'static,
+  {
+    DispatcherImpl::dispatch_async(&self.sender, message, self.timeout).await
+  }
+
+  pub fn dispatch_sync_nowait<M>(&self, message: M)
+    where
+      C: ComponentMessageHandler<M>,
+      M: Send + 'static,
+  {
+    DispatcherImpl::dispatch_sync_nowait(&self.sender, message)
+  }
+
+  pub fn dispatch_sync<M>(&self, message: M) -> C::Answer
+    where
+      C: ComponentMessageHandler<M>,
+      M: Send + 'static,
+  {
+    DispatcherImpl::dispatch_sync(&self.sender, message, self.timeout)
+  }
+
+  pub async fn try_dispatch<M>(&self, message: M) -> Result<<C as ComponentMessageHandler<M>>::Answer, DispatchError>
+    where
+      C: ComponentMessageHandler<M>,
+      M: Send + 'static,
+  {
+    DispatcherImpl::try_dispatch_async(&self.sender, message, self.timeout).await
+  }
+
+  pub async fn try_dispatch_timeout<M>(&self, message: M, timeout: Duration) -> Result<<C as ComponentMessageHandler<M>>::Answer, DispatchError>
+    where
+      C: ComponentMessageHandler<M>,
+      M: Send + 'static,
+  {
+    DispatcherImpl::try_dispatch_async(&self.sender, message, Some(timeout)).await
+  }
+
+  pub fn try_dispatch_sync_nowait<M>(&self, message: M) -> Result<(), DispatchError>
+    where
+      C: ComponentMessageHandler<M>,
+      M: Send + 'static,
+  {
+    DispatcherImpl::try_dispatch_sync_nowait(&self.sender, message)
+  }
+
+  pub fn try_dispatch_sync<M>(&self, message: M) -> Result<C::Answer, DispatchError>
+    where
+      C: ComponentMessageHandler<M>,
+      M: Send + 'static,
+  {
+    DispatcherImpl::try_dispatch_sync(&self.sender, message, self.timeout)
+  }
+
+  /// Dispatches the message built by `message` and streams the items its handler sends
+  /// through the given [`ResponseSender`].
+  pub async fn dispatch_stream<M, T, F>(&self, message: F) -> ResponseStream<T>
+    where
+      C: ComponentMessageHandler<M>,
+      M: Send + 'static,
+      T: Send + 'static,
+      F: FnOnce(ResponseSender<T>) -> M,
+  {
+    DispatcherImpl::dispatch_stream(&self.sender, message).await
+  }
+
+  pub fn make_sender<M>(&self) -> MessageSender<M, <C as ComponentMessageHandler<M>>::Answer>
+    where
+      C: ComponentMessageHandler<M>,
+      M: Send + 'static,
+  {
+    MessageSender::<M, C::Answer>::create(self.sender.clone(), self.timeout)
+  }
+
+  /// Dispatches every item of `stream` in order, waiting for each answer before pulling the next item.
+  /// The attachment does not keep the component alive.
+  pub fn attach_stream<S>(&self, stream: S) -> StreamAttachment
+    where
+      S: Stream + Send + 'static,
+      S::Item: Send + 'static,
+      C: ComponentMessageHandler<S::Item>,
+  {
+    DispatcherImpl::forward_stream(self.sender.downgrade(), stream)
+  }
+
+  pub fn attach_receiver<M>(&self, receiver: mpsc::Receiver<M>) -> StreamAttachment
+    where
+      M: Send + 'static,
+      C: ComponentMessageHandler<M>,
+  {
+    self.attach_stream(receiver_stream(receiver))
+  }
+
+  pub fn make_transforming_sender<M, N, T>(
+    &self,
+    transformer: T,
+  ) -> MessageSender<M, <C as ComponentMessageHandler<N>>::Answer>
+    where
+      C: ComponentMessageHandler<N>,
+      M: Send + 'static,
+      N: Send + 'static,
+      T: Fn(M) -> N + Send + Sync + 'static,
+  {
+    MessageSender::<M, C::Answer>::create_transforming(self.sender.clone(), self.timeout, Arc::new(transformer))
+  }
+}
+
+impl<C> Clone for ComponentHandle<C>
+  where
+    C: Component,
+{
+  fn clone(&self) -> Self {
+    let sender = self.sender.clone();
+    let control = self.control.clone();
+    let timeout = self.timeout;
+
+    Self { sender, control, timeout }
+  }
+}
+
+#[derive(Debug)]
+pub struct WeakComponentHandle<C>
+  where
+    C: Component,
+{
+  sender: MailboxWeakSender<AnyComponentMessage<C>>,
+  control: Arc<ComponentControl<C>>,
+  timeout: Option<Duration>,
+}
+
+impl<C> WeakComponentHandle<C>
+  where
+    C: Component,
+{
+  pub fn id(&self) -> ActorId {
+    ActorId(self.sender.id())
+  }
+
+  /// Returns a strong handle unless the component stopped accepting messages.
+  pub fn upgrade(&self) -> Option<ComponentHandle<C>> {
+    let sender = self.sender.upgrade()?;
+    let control = self.control.clone();
+    let timeout = self.timeout;
+
+    Some(ComponentHandle { sender, control, timeout })
+  }
+
+  pub async fn terminated(&self) {
+    self.control.terminated().await
+  }
+}
+
+/// Identifies a running component, see [`ComponentHandle::id`] and [`Context::sender`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ActorId(usize);
+
+/// Runner state a handler can take as `ctx: &mut Context<Self>` parameter.
+#[derive(Debug)]
+pub struct Context<C>
+  where
+    C: Component,
+{
+  handle: WeakComponentHandle<C>,
+  sender: Option<ActorId>,
+  children: JoinSet<()>,
+}
+
+impl<C> Context<C>
+  where
+    C: Component,
+{
+  fn new(handle: WeakComponentHandle<C>) -> Self {
+    Self { handle, sender: None, children: JoinSet::new() }
+  }
+
+  pub fn id(&self) -> ActorId {
+    ActorId(self.handle.sender.id())
+  }
+
+  /// Component whose handler dispatched the current message, if it came from one.
+  pub fn sender(&self) -> Option<ActorId> {
+    self.sender
+  }
+
+  /// Own handle, unless the component stopped accepting messages.
+  pub fn handle(&self) -> Option<C::HandleWrapper> {
+    self.handle.upgrade().map(C::create_wrapper)
+  }
+
+  pub fn weak_handle(&self) -> WeakComponentHandle<C> {
+    self.handle.clone()
+  }
+
+  /// Stops the component once the current message is handled, see [`ComponentHandle::stop`].
+  pub fn stop(&self) {
+    self.handle.control.stop()
+  }
+
+  /// Runs `future` as a child task, which is aborted when the component terminates.
+  pub fn spawn<F>(&mut self, future: F)
+    where
+      F: Future<Output=()> + Send + 'static,
+  {
+    while self.children.try_join_next().is_some() {}
+
+    self.children.spawn(future);
+  }
+
+  /// Sends `message` to the component after `delay`, unless cancelled or the component stopped.
+  pub fn run_later<M>(&mut self, delay: Duration, message: M) -> TimerHandle
+    where
+      C: ComponentMessageHandler<M>,
+      M: Send + 'static,
+  {
+    let handle = self.handle.clone();
+
+    self.spawn_timer(async move {
+      tokio::time::sleep(delay).await;
+
+      if let Some(handle) = handle.upgrade() {
+        drop(handle.try_dispatch_sync_nowait(message));
+      }
+    })
+  }
+
+  /// Sends a clone of `message` to the component every `period`, starting after the first one.
+  pub fn run_interval<M>(&mut self, period: Duration, message: M) -> TimerHandle
+    where
+      C: ComponentMessageHandler<M>,
+      M: Clone + Send + 'static,
+  {
+    let handle = self.handle.clone();
+
+    self.spawn_timer(async move {
+      let mut interval = tokio::time::interval_at(Instant::now() + period, period);
+
+      loop {
+        interval.tick().await;
+
+        let sent = match handle.upgrade() {
+          Some(handle) => handle.try_dispatch_sync_nowait(message.clone()),
+          None => return,
+        };
+        if let Err(DispatchError::MailboxClosed) = sent {
+          return;
+        }
+      }
+    })
+  }
+
+  fn spawn_timer<F>(&mut self, timer: F) -> TimerHandle
+    where
+      F: Future<Output=()> + Send + 'static,
+  {
+    while self.children.try_join_next().is_some() {}
+
+    TimerHandle(self.children.spawn(timer))
+  }
+}
+
+/// Scheduled message of [`Context::run_later`] or [`Context::run_interval`].
+/// Timers are cancelled when the component terminates.
+#[derive(Debug)]
+pub struct TimerHandle(AbortHandle);
+
+impl TimerHandle {
+  pub fn cancel(&self) {
+    self.0.abort()
+  }
+
+  pub fn is_finished(&self) -> bool {
+    self.0.is_finished()
+  }
+}
+
+impl<C> Clone for WeakComponentHandle<C>
+  where
+    C: Component,
+{
+  fn clone(&self) -> Self {
+    let sender = self.sender.clone();
+    let control = self.control.clone();
+    let timeout = self.timeout;
+
+    Self { sender, control, timeout }
+  }
+}
+
+/// Succeeds for the last strong handle of a component, otherwise hands it back.
+impl<C> TryFrom<ComponentHandle<C>> for ComponentHandleUnique<C> where C: Component {
+  type Error = ComponentHandle<C>;
+
+  fn try_from(value: ComponentHandle<C>) -> Result<Self, Self::Error> {
+    let control = &value.control;
+    if value.sender.when_unique(|| control.owned.store(true, Ordering::Release)).is_none() {
+      return Err(value);
+    }
+
+    Ok(Self {
+      sender: value.sender,
+      control: value.control,
+      timeout: value.timeout,
+    })
+  }
+}
+
+/// Handle owning its component. Dropping it stops the component, and only the owner can take it back.
+/// Weak handles can still be upgraded to message the component while it runs, so the handle is only
+/// unique with respect to `stop_and_take`: handles upgraded later or returned by [`Context::handle`]
+/// can stop the component, but [`ComponentHandle::stop_and_take`] returns `None` for them.
+#[derive(Debug)]
+pub struct ComponentHandleUnique<C>
+  where
+    C: Component,
+{
+  sender: MailboxSender<AnyComponentMessage<C>>,
+  control: Arc<ComponentControl<C>>,
+  timeout: Option<Duration>,
+}
+
+impl<C> ComponentHandleUnique<C>
+  where
+    C: Component,
+{
+  pub fn stop(&self) {
+    self.sender.close();
+    self.control.stop()
+  }
+
+  pub fn abort(&self) {
+    self.control.abort()
+  }
+
+  pub fn with_timeout(mut self, timeout: Duration) -> Self {
+    self.timeout = Some(timeout);
+    self
+  }
+
+  pub async fn terminated(&self) {
+    self.control.terminated().await
+  }
+
+  /// Stops the component like [`Self::stop`] and hands it back. Returns `None` only if the
+  /// component already terminated.
+  pub async fn stop_and_take(self) -> Option<C>
+    where
+      C: Send,
+  {
+    self.sender.close();
+    self.control.stop_and_take().await
+  }
+
+  pub fn downgrade(&self) -> WeakComponentHandle<C> {
+    WeakComponentHandle {
+      sender: self.sender.downgrade(),
+      control: self.control.clone(),
+      timeout: self.timeout,
+    }
+  }
+
+  pub async fn dispatch<M>(&self, message: M) -> <C as ComponentMessageHandler<M>>::Answer
+    where
+      C: ComponentMessageHandler<M>,
+      M: Send + 'static,
+  {
+    DispatcherImpl::dispatch_async(&self.sender, message, self.timeout).await
+  }
+
+  pub fn dispatch_sync_nowait<M>(&self, message: M)
+    where
+      C: ComponentMessageHandler<M>,
+      M: Send + 'static,
+  {
+    DispatcherImpl::dispatch_sync_nowait(&self.sender, message)
+  }
+
+  pub fn dispatch_sync<M>(&self, message: M) -> C::Answer
+    where
+      C: ComponentMessageHandler<M>,
+      M: Send + 'static,
+  {
+    DispatcherImpl::dispatch_sync(&self.sender, message, self.timeout)
+  }
+
+  pub async fn try_dispatch<M>(&self, message: M) -> Result<<C as ComponentMessageHandler<M>>::Answer, DispatchError>
+    where
+      C: ComponentMessageHandler<M>,
+      M: Send + 'static,
+  {
+    DispatcherImpl::try_dispatch_async(&self.sender, message, self.timeout).await
+  }
+
+  pub async fn try_dispatch_timeout<M>(&self, message: M, timeout: Duration) -> Result<<C as ComponentMessageHandler<M>>::Answer, DispatchError>
+    where
+      C: ComponentMessageHandler<M>,
+      M: Send + 'static,
+  {
+    DispatcherImpl::try_dispatch_async(&self.sender, message, Some(timeout)).await
+  }
+
+  pub fn try_dispatch_sync_nowait<M>(&self, message: M) -> Result<(), DispatchError>
+    where
+      C: ComponentMessageHandler<M>,
+      M: Send + 'static,
+  {
+    DispatcherImpl::try_dispatch_sync_nowait(&self.sender, message)
+  }
+
+  pub fn try_dispatch_sync<M>(&self, message: M) -> Result<C::Answer, DispatchError>
+    where
+      C: ComponentMessageHandler<M>,
+      M: Send + 'static,
+  {
+    DispatcherImpl::try_dispatch_sync(&self.sender, message, self.timeout)
+  }
+
+  /// Dispatches the message built by `message` and streams the items its handler sends
+  /// through the given [`ResponseSender`].
+  pub async fn dispatch_stream<M, T, F>(&self, message: F) -> ResponseStream<T>
+    where
+      C: ComponentMessageHandler<M>,
+      M: Send + 'static,
+      T: Send + 'static,
+      F: FnOnce(ResponseSender<T>) -> M,
+  {
+    DispatcherImpl::dispatch_stream(&self.sender, message).await
+  }
+
+  pub fn make_sender<M>(&self) -> MessageSender<M, <C as ComponentMessageHandler<M>>::Answer>
+    where
+      C: ComponentMessageHandler<M>,
+      M: Send + 'static,
+  {
+    MessageSender::<M, C::Answer>::create(self.sender.clone(), self.timeout)
+  }
+
+  /// Dispatches every item of `stream` in order, waiting for each answer before pulling the next item.
+  /// The attachment does not keep the component alive.
+  pub fn attach_stream<S>(&self, stream: S) -> StreamAttachment
+    where
+      S: Stream + Send + 'static,
+      S::Item: Send + 'static,
+      C: ComponentMessageHandler<S::Item>,
+  {
+    DispatcherImpl::forward_stream(self.sender.downgrade(), stream)
+  }
+
+  pub fn attach_receiver<M>(&self, receiver: mpsc::Receiver<M>) -> StreamAttachment
+    where
+      M: Send + 'static,
+      C: ComponentMessageHandler<M>,
+  {
+    self.attach_stream(receiver_stream(receiver))
+  }
+
+  pub fn make_transforming_sender<M, N, T>(
+    &self,
+    transformer: T,
+  ) -> MessageSender<M, <C as ComponentMessageHandler<N>>::Answer>
+    where
+      C: ComponentMessageHandler<N>,
+      M: Send + 'static,
+      N: Send + 'static,
+      T: Fn(M) -> N + Send + Sync + 'static,
+  {
+    MessageSender::<M, C::Answer>::create_transforming(self.sender.clone(), self.timeout, Arc::new(transformer))
+  }
+}
+
+impl<C> Drop for ComponentHandleUnique<C>
+  where
+    C: Component,
+{
+  fn drop(&mut self) {
+    self.stop();
+  }
+}
+
+
+type FutureMessageDispatcher<M, R> =
+Arc<dyn Fn(M, Option<Duration>) -> Pin<Box<dyn Future<Output=Result<R, DispatchError>> + Send>> + Send + Sync>;
+type SyncNowaitMessageDispatcher<M> = Arc<dyn Fn(M) -> Result<(), DispatchError> + Send + Sync>;
+type SyncMessageDispatcher<M, R> = Arc<dyn Fn(M, Option<Duration>) -> Result<R, DispatchError> + Send + Sync>;
+type MessageSenderUpgrade<M, R> = Arc<dyn Fn() -> Option<MessageSender<M, R>> + Send + Sync>;
+type MessageSenderClosed = Arc<dyn Fn() -> bool + Send + Sync>;
+type MessageTransformer<M, N> = Arc<dyn Fn(M) -> N + Send + Sync>;
+
+#[derive(Clone)]
+pub struct MessageSender<M, R> {
+  async_dispatcher: FutureMessageDispatcher<M, R>,
+  sync_nowait_dispatcher: SyncNowaitMessageDispatcher<M>,
+  sync_dispatcher: SyncMessageDispatcher<M, R>,
+  weak: WeakMessageSender<M, R>,
+  timeout: Option<Duration>,
+}
+
+impl<M, R> MessageSender<M, R> {
+  fn create<C>(sender: MailboxSender<AnyComponentMessage<C>>, timeout: Option<Duration>) -> Self
+    where
+      C: ComponentMessageHandler<M, Answer=R>,
+      M: Send + 'static,
+      R: Send,
+  {
+    let fut_sender = sender.clone();
+    let async_dispatcher =
+      move |message: M, timeout: Option<Duration>| -> Pin<Box<dyn Future<Output=Result<C::Answer, DispatchError>> + Send>> {
+        let fut_sender = fut_sender.clone();
+
+        Box::pin(async move { DispatcherImpl::try_dispatch_async(&fut_sender, message, timeout).await })
+      };
+
+    let nowait_sender = sender.clone();
+    let sync_nowait_dispatcher =
+      move |message: M| DispatcherImpl::try_dispatch_sync_nowait(&nowait_sender, message);
+
+    let weak_sender = sender.downgrade();
+    let upgrade = move || weak_sender.upgrade().map(|sender| Self::create(sender, None));
+
+    let weak_sender = sender.downgrade();
+    let weak_nowait_dispatcher =
+      move |message: M| DispatcherImpl::try_dispatch_weak_nowait(&weak_sender, message);
+
+    let weak_sender = sender.downgrade();
+    let is_closed = move || weak_sender.is_closed();
+
+    let sync_dispatcher =
+      move |message: M, timeout: Option<Duration>| DispatcherImpl::try_dispatch_sync(&sender, message, timeout);
+
+    Self {
+      async_dispatcher: Arc::new(async_dispatcher),
+      sync_nowait_dispatcher: Arc::new(sync_nowait_dispatcher),
+      sync_dispatcher: Arc::new(sync_dispatcher),
+      weak: WeakMessageSender {
+        upgrade: Arc::new(upgrade),
+        sync_nowait_dispatcher: Arc::new(weak_nowait_dispatcher),
+        is_closed: Arc::new(is_closed),
+        timeout: None,
+      },
+      timeout,
+    }
+  }
+
+  fn create_transforming<C, N>(
+    sender: MailboxSender<AnyComponentMessage<C>>,
+    timeout: Option<Duration>,
+    transformer: MessageTransformer<M, N>,
+  ) -> Self
+    where
+      C: ComponentMessageHandler<N, Answer=R>,
+      M: Send + 'static,
+      N: Send + 'static,
+      R: Send,
+  {
+    let fut_sender = sender.clone();
+
+    let upgrade_transformer = transformer.clone();
+    let async_dispatcher =
+      move |message: M, timeout: Option<Duration>| -> Pin<Box<dyn Future<Output=Result<C::Answer, DispatchError>> + Send>> {
+        let fut_sender = fut_sender.clone();
+        let transformer = upgrade_transformer.clone();
+
+        let message = (*transformer)(message);
+
+        Box::pin(async move { DispatcherImpl::try_dispatch_async(&fut_sender, message, timeout).await })
+      };
+
+    let nowait_sender = sender.clone();
+
+    let upgrade_transformer = transformer.clone();
+    let sync_nowait_dispatcher = move |message: M| {
+      let transformer = upgrade_transformer.clone();
+      let message = (*transformer)(message);
+
+      DispatcherImpl::try_dispatch_sync_nowait(&nowait_sender, message)
+    };
+
+    let weak_sender = sender.downgrade();
+    let upgrade_transformer = transformer.clone();
+    let upgrade = move || {
+      weak_sender.upgrade().map(|sender| Self::create_transforming(sender, None, upgrade_transformer.clone()))
+    };
+
+    let weak_sender = sender.downgrade();
+    let upgrade_transformer = transformer.clone();
+    let weak_nowait_dispatcher = move |message: M| {
+      let message = (*upgrade_transformer)(message);
+
+      DispatcherImpl::try_dispatch_weak_nowait(&weak_sender, message)
+    };
+
+    let weak_sender = sender.downgrade();
+    let is_closed = move || weak_sender.is_closed();
+
+    let sync_dispatcher = move |message: M, timeout: Option<Duration>| {
+      let message = (*transformer)(message);
+
+      DispatcherImpl::try_dispatch_sync(&sender, message, timeout)
+    };
+
+    Self {
+      async_dispatcher: Arc::new(async_dispatcher),
+      sync_nowait_dispatcher: Arc::new(sync_nowait_dispatcher),
+      sync_dispatcher: Arc::new(sync_dispatcher),
+      weak: WeakMessageSender {
+        upgrade: Arc::new(upgrade),
+        sync_nowait_dispatcher: Arc::new(weak_nowait_dispatcher),
+        is_closed: Arc::new(is_closed),
+        timeout: None,
+      },
+      timeout,
+    }
+  }
+
+  pub fn with_timeout(mut self, timeout: Duration) -> Self {
+    self.timeout = Some(timeout);
+    self
+  }
+
+  /// Creates a sender that does not keep the component running.
+  pub fn downgrade(&self) -> WeakMessageSender<M, R> {
+    WeakMessageSender {
+      timeout: self.timeout,
+      ..self.weak.clone()
+    }
+  }
+
+  pub async fn dispatch(&self, message: M) -> R {
+    DispatcherImpl::unwrap_answer(self.try_dispatch(message).await)
+  }
+
+  pub fn dispatch_sync_nowait(&self, message: M) {
+    let _ = self.try_dispatch_sync_nowait(message);
+  }
+
+  pub fn dispatch_sync(&self, message: M) -> R {
+    DispatcherImpl::unwrap_answer(self.try_dispatch_sync(message))
+  }
+
+  pub async fn try_dispatch(&self, message: M) -> Result<R, DispatchError> {
+    (self.async_dispatcher)(message, self.timeout).await
+  }
+
+  pub async fn try_dispatch_timeout(&self, message: M, timeout: Duration) -> Result<R, DispatchError> {
+    (self.async_dispatcher)(message, Some(timeout)).await
+  }
+
+  pub fn try_dispatch_sync_nowait(&self, message: M) -> Result<(), DispatchError> {
+    (self.sync_nowait_dispatcher)(message)
+  }
+
+  pub fn try_dispatch_sync(&self, message: M) -> Result<R, DispatchError> {
+    (self.sync_dispatcher)(message, self.timeout)
+  }
+}
+
+pub struct WeakMessageSender<M, R> {
+  upgrade: MessageSenderUpgrade<M, R>,
+  sync_nowait_dispatcher: SyncNowaitMessageDispatcher<M>,
+  is_closed: MessageSenderClosed,
+  timeout: Option<Duration>,
+}
+
+impl<M, R> WeakMessageSender<M, R> {
+  /// Returns a strong sender unless the component stopped accepting messages.
+  pub fn upgrade(&self) -> Option<MessageSender<M, R>> {
+    let mut sender = (self.upgrade)()?;
+    sender.timeout = self.timeout;
+    Some(sender)
+  }
+
+  /// Whether the component stopped accepting messages. Cheaper than checking [`Self::upgrade`].
+  pub fn is_closed(&self) -> bool {
+    (self.is_closed)()
+  }
+
+  /// Queues `message` like [`MessageSender::try_dispatch_sync_nowait`] without upgrading.
+  pub fn try_dispatch_sync_nowait(&self, message: M) -> Result<(), DispatchError> {
+    (self.sync_nowait_dispatcher)(message)
+  }
+}
+
+impl<M, R> Clone for WeakMessageSender<M, R> {
+  fn clone(&self) -> Self {
+    Self {
+      upgrade: self.upgrade.clone(),
+      sync_nowait_dispatcher: self.sync_nowait_dispatcher.clone(),
+      is_closed: self.is_closed.clone(),
+      timeout: self.timeout,
+    }
+  }
+}
+
+/// Senders of possibly different component types addressed as one.
+#[derive(Clone)]
+pub struct RecipientGroup<M, R> {
+  recipients: Vec<MessageSender<M, R>>,
+}
+
+impl<M, R> RecipientGroup<M, R> {
+  pub fn new() -> Self {
+    Self { recipients: vec![] }
+  }
+
+  pub fn push(&mut self, recipient: MessageSender<M, R>) {
+    self.recipients.push(recipient);
+  }
+
+  pub fn len(&self) -> usize {
+    self.recipients.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.recipients.is_empty()
+  }
+}
+
+impl<M, R> RecipientGroup<M, R>
+  where
+    M: Clone,
+{
+  /// Queues `message` at every recipient, returns the outcome per recipient in group order.
+  pub fn broadcast_nowait(&self, message: M) -> Vec<Result<(), DispatchError>> {
+    self.recipients.iter()
+      .map(|recipient| recipient.try_dispatch_sync_nowait(message.clone()))
+      .collect()
+  }
+
+  /// Dispatches `message` to all recipients concurrently, returns the answers in group order.
+  pub async fn broadcast(&self, message: M) -> Vec<Result<R, DispatchError>> {
+    let dispatches = self.recipients.iter()
+      .map(|recipient| recipient.try_dispatch(message.clone()));
+
+    futures::future::join_all(dispatches).await
+  }
+
+  /// Returns the first successful answer, or every error if no recipient answered.
+  pub async fn first_answer(&self, message: M) -> Result<R, Vec<DispatchError>> {
+    self.gather(message, 1).await.map(|mut answers| answers.remove(0))
+  }
+
+  /// Returns the first `quorum` successful answers in the order they arrived. Fails with the errors collected so
+  /// far as soon as too few recipients are left to reach the quorum.
+  ///
+  /// Dispatches still running when the result is known are abandoned, see [`DispatchError::Cancelled`].
+  pub async fn gather(&self, message: M, quorum: usize) -> Result<Vec<R>, Vec<DispatchError>> {
+    let mut pending = self.recipients.iter()
+      .map(|recipient| recipient.try_dispatch(message.clone()))
+      .collect::<FuturesUnordered<_>>();
+    let mut answers = Vec::with_capacity(quorum);
+    let mut errors = vec![];
+
+    while answers.len() < quorum {
+      if answers.len() + pending.len() < quorum {
+        return Err(errors);
+      }
+
+      match pending.next().await {
+        Some(Ok(answer)) => answers.push(answer),
+        Some(Err(error)) => errors.push(error),
+        None => return Err(errors),
+      }
+    }
+
+    Ok(answers)
+  }
+}
+
+impl<M, R> Default for RecipientGroup<M, R> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl<M, R> FromIterator<MessageSender<M, R>> for RecipientGroup<M, R> {
+  fn from_iter<I: IntoIterator<Item=MessageSender<M, R>>>(iter: I) -> Self {
+    Self { recipients: iter.into_iter().collect() }
+  }
+}
+
+impl<M, R> Extend<MessageSender<M, R>> for RecipientGroup<M, R> {
+  fn extend<I: IntoIterator<Item=MessageSender<M, R>>>(&mut self, iter: I) {
+    self.recipients.extend(iter)
+  }
+}
+
+/// How an attached stream stopped feeding its component.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamEnd {
+  /// The stream has no more items.
+  Exhausted,
+  /// The component stopped accepting messages.
+  Closed,
+  /// The attachment was cancelled.
+  Cancelled,
+}
+
+/// Forwarding of a stream attached with [`ComponentHandle::attach_stream`].
+#[derive(Debug)]
+pub struct StreamAttachment {
+  task: JoinHandle<StreamEnd>,
+}
+
+impl StreamAttachment {
+  /// Stops forwarding. The item currently dispatched is still handled.
+  pub fn cancel(&self) {
+    self.task.abort()
+  }
+
+  pub async fn finished(self) -> StreamEnd {
+    match self.task.await {
+      Ok(end) => end,
+      Err(error) if error.is_cancelled() => StreamEnd::Cancelled,
+      Err(error) => std::panic::resume_unwind(error.into_panic()),
+    }
+  }
+}
+
+const RESPONSE_STREAM_CAPACITY: usize = 16;
+
+/// Sending half of a [`ResponseStream`], taken as parameter by streaming handlers.
+#[derive(Debug)]
+pub struct ResponseSender<T>(mpsc::Sender<T>);
+
+impl<T> ResponseSender<T> {
+  /// Waits until the caller has room for `item`. Fails with [`DispatchError::Cancelled`] once
+  /// the caller dropped the stream.
+  pub async fn send(&self, item: T) -> Result<(), DispatchError> {
+    self.0.send(item).await.map_err(|_| DispatchError::Cancelled)
+  }
+
+  pub fn is_closed(&self) -> bool {
+    self.0.is_closed()
+  }
+}
+
+impl<T> Clone for ResponseSender<T> {
+  fn clone(&self) -> Self {
+    Self(self.0.clone())
+  }
+}
+
+/// Items sent by a streaming handler. The stream ends once every [`ResponseSender`] is dropped,
+/// dropping it early cancels the message.
+pub struct ResponseStream<T> {
+  items: mpsc::Receiver<T>,
+  answer: Option<PinnedFuture<'static>>,
+  error: Option<DispatchError>,
+}
+
+impl<T> ResponseStream<T> {
+  /// Why the handler did not run to completion, known once the stream ended.
+  pub fn error(&self) -> Option<&DispatchError> {
+    self.error.as_ref()
+  }
+}
+
+impl<T> Stream for ResponseStream<T> {
+  type Item = T;
+
+  fn poll_next(self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Option<T>> {
+    let this = self.get_mut();
+
+    match this.items.poll_recv(cx) {
+      Poll::Ready(Some(item)) => return Poll::Ready(Some(item)),
+      Poll::Ready(None) => {}
+      Poll::Pending => return Poll::Pending,
+    }
+
+    if let Some(answer) = this.answer.as_mut() {
+      match answer.as_mut().poll(cx) {
+        Poll::Ready(result) => {
+          this.error = result.err();
+          this.answer = None;
+        }
+        Poll::Pending => return Poll::Pending,
+      }
+    }
+    Poll::Ready(None)
+  }
+}
+
+impl<T> std::fmt::Debug for ResponseStream<T> {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("ResponseStream")
+      .field("items", &self.items)
+      .field("error", &self.error)
+      .finish()
+  }
+}
+
+fn receiver_stream<M>(receiver: mpsc::Receiver<M>) -> impl Stream<Item=M> {
+  futures::stream::unfold(receiver, |mut receiver| async move {
+    receiver.recv().await.map(|item| (item, receiver))
+  })
+}
+
+struct DispatcherImpl;
+
+impl DispatcherImpl {
+  fn forward_stream<C, S>(sender: MailboxWeakSender<AnyComponentMessage<C>>, stream: S) -> StreamAttachment
+    where
+      C: ComponentMessageHandler<S::Item>,
+      S: Stream + Send + 'static,
+      S::Item: Send + 'static,
+  {
+    let task = tokio::spawn(async move {
+      tokio::pin!(stream);
+
+      while let Some(item) = stream.next().await {
+        let sender = match sender.upgrade() {
+          Some(sender) => sender,
+          None => return StreamEnd::Closed,
+        };
+        if let Err(DispatchError::MailboxClosed) = Self::try_dispatch_async(&sender, item, None).await {
+          return StreamEnd::Closed;
+        }
+      }
+      StreamEnd::Exhausted
+    });
+
+    StreamAttachment { task }
+  }
+
+  async fn dispatch_async<C, M>(
+    sender: &MailboxSender<AnyComponentMessage<C>>,
+    message: M,
+    timeout: Option<Duration>,
+  ) -> C::Answer
+    where
+      C: ComponentMessageHandler<M>,
+      M: Send + 'static,
+  {
+    Self::unwrap_answer(Self::try_dispatch_async(sender, message, timeout).await)
+  }
+
+  async fn try_dispatch_async<C, M>(
+    sender: &MailboxSender<AnyComponentMessage<C>>,
+    message: M,
+    timeout: Option<Duration>,
+  ) -> Result<C::Answer, DispatchError>
+    where
+      C: ComponentMessageHandler<M>,
+      M: Send + 'static,
+  {
+    let call_chain = Self::call_chain(sender)?;
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let (resolvable, resolver) = AsyncResolvable::new_with_meta(message);
+    let message = Self::make_message(resolver, deadline, call_chain);
+
+    let answer = async move {
+      Self::sent(sender.send(message).await)?;
+      resolvable.await.unwrap_or(Err(DispatchError::MailboxClosed))
+    };
+
+    match deadline {
+      Some(deadline) => tokio::time::timeout_at(deadline, answer).await.unwrap_or(Err(DispatchError::Timeout)),
+      None => answer.await,
+    }
+  }
+
+  async fn dispatch_stream<C, M, T, F>(
+    sender: &MailboxSender<AnyComponentMessage<C>>,
+    message: F,
+  ) -> ResponseStream<T>
+    where
+      C: ComponentMessageHandler<M>,
+      M: Send + 'static,
+      T: Send + 'static,
+      F: FnOnce(ResponseSender<T>) -> M,
+  {
+    let (responses, items) = mpsc::channel(RESPONSE_STREAM_CAPACITY);
+    let message = message(ResponseSender(responses));
+
+    let answer: PinnedFuture<'static> = match Self::enqueue(sender, message).await {
+      Ok(resolvable) => Box::pin(async move {
+        resolvable.await.unwrap_or(Err(DispatchError::MailboxClosed)).map(drop)
+      }),
+      Err(error) => Box::pin(std::future::ready(Err(error))),
+    };
+
+    ResponseStream { items, answer: Some(answer), error: None }
+  }
+
+  async fn enqueue<C, M>(
+    sender: &MailboxSender<AnyComponentMessage<C>>,
+    message: M,
+  ) -> Result<AsyncResolvable<Result<C::Answer, DispatchError>>, DispatchError>
+    where
+      C: ComponentMessageHandler<M>,
+      M: Send + 'static,
+  {
+    let call_chain = Self::call_chain(sender)?;
+    let (resolvable, resolver) = AsyncResolvable::new_with_meta(message);
+
+    Self::sent(sender.send(Self::make_message(resolver, None, call_chain)).await)?;
+    Ok(resolvable)
+  }
+
+  fn dispatch_sync_nowait<C, M>(sender: &MailboxSender<AnyComponentMessage<C>>, message: M)
+    where
+      C: ComponentMessageHandler<M>,
+      M: Send + 'static,
+  {
+    let _ = Self::try_dispatch_sync_nowait(sender, message);
+  }
+
+  fn try_dispatch_sync_nowait<C, M>(
+    sender: &MailboxSender<AnyComponentMessage<C>>,
+    message: M,
+  ) -> Result<(), DispatchError>
+    where
+      C: ComponentMessageHandler<M>,
+      M: Send + 'static,
+  {
+    Self::sent(sender.try_send(Self::make_nowait_message(message)))
+  }
+
+  fn try_dispatch_weak_nowait<C, M>(
+    sender: &MailboxWeakSender<AnyComponentMessage<C>>,
+    message: M,
+  ) -> Result<(), DispatchError>
+    where
+      C: ComponentMessageHandler<M>,
+      M: Send + 'static,
+  {
+    Self::sent(sender.try_send(Self::make_nowait_message(message)))
+  }
+
+  fn make_nowait_message<C, M>(message: M) -> AnyComponentMessage<C>
+    where
+      C: ComponentMessageHandler<M>,
+      M: Send + 'static,
+  {
+    let resolver = Resolver::<M, Result<C::Answer, DispatchError>>::noop(message);
+    Self::make_message(resolver, None, vec![])
+  }
+
+  fn dispatch_sync<C, M>(
+    sender: &MailboxSender<AnyComponentMessage<C>>,
+    message: M,
+    timeout: Option<Duration>,
+  ) -> C::Answer
+    where
+      C: ComponentMessageHandler<M>,
+      M: Send + 'static,
+  {
+    Self::unwrap_answer(Self::try_dispatch_sync(sender, message, timeout))
+  }
+
+  /// Blocking dispatches cannot give up waiting for a running handler, so `timeout` only
+  /// skips the message if it is still queued when the deadline passes.
+  fn try_dispatch_sync<C, M>(
+    sender: &MailboxSender<AnyComponentMessage<C>>,
+    message: M,
+    timeout: Option<Duration>,
+  ) -> Result<C::Answer, DispatchError>
+    where
+      C: ComponentMessageHandler<M>,
+      M: Send + 'static,
+  {
+    let call_chain = Self::call_chain(sender)?;
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let (resolvable, resolver) = SyncResolvable::new_with_meta(message);
+    let message = Self::make_message(resolver, deadline, call_chain);
+
+    Self::sent(sender.blocking_send(message))?;
+    resolvable.wait().unwrap_or(Err(DispatchError::MailboxClosed))
+  }
+
+  fn sent<C>(result: Result<Vec<AnyComponentMessage<C>>, TrySendError<AnyComponentMessage<C>>>) -> Result<(), DispatchError>
+    where
+      C: Component,
+  {
+    match result {
+      Ok(evicted) => {
+        for message in evicted {
+          message.reject(DispatchError::Dropped);
+        }
+        Ok(())
+      }
+      Err(TrySendError::Full(_)) => Err(DispatchError::Full),
+      Err(TrySendError::Closed(_)) => Err(DispatchError::MailboxClosed),
+    }
+  }
+
+  fn unwrap_answer<A>(answer: Result<A, DispatchError>) -> A {
+    answer.unwrap_or_else(|error| panic!("failed to dispatch message: {}", error))
+  }
+
+  /// Components whose handlers are waiting on the current task. Waiting for one of them again
+  /// would never finish.
+  fn call_chain<C>(sender: &MailboxSender<AnyComponentMessage<C>>) -> Result<Vec<ActorId>, DispatchError>
+    where
+      C: Component,
+  {
+    let call_chain = CALL_CHAIN.try_with(Vec::clone).unwrap_or_default();
+
+    if call_chain.contains(&ActorId(sender.id())) {
+      return Err(DispatchError::Deadlock);
+    }
+    Ok(call_chain)
+  }
+
+  fn make_message<C, M>(
+    resolver: Resolver<M, Result<C::Answer, DispatchError>>,
+    deadline: Option<Instant>,
+    call_chain: Vec<ActorId>,
+  ) -> AnyComponentMessage<C>
+    where
+      C: ComponentMessageHandler<M>,
+      M: Send + 'static,
+  {
+    AnyComponentMessage {
+      payload: Container::new(resolver).into_raw(),
+      dispatcher: |component, context, data| {
+        <C as ComponentMessageHandler<M>>::dispatch(component, context, data)
+      },
+      shared_dispatcher: <C as ComponentMessageHandler<M>>::SHARED_DISPATCH,
+      rejecter: |data, error| {
+        let resolver = unsafe {
+          Container::<Resolver<M, Result<C::Answer, DispatchError>>>::from_raw(data.0)
+        }.into_inner();
+
+        resolver.resolve(Err(error));
+      },
+      cancelled: |data| {
+        let resolver = ManuallyDrop::new(unsafe {
+          Container::<Resolver<M, Result<C::Answer, DispatchError>>>::from_raw(data.0)
+        });
+
+        resolver.is_closed()
+      },
+      kind: TypeId::of::<M>(),
+      name: type_name::<M>(),
+      priority: <C as ComponentMessageHandler<M>>::PRIORITY,
+      deadline,
+      call_chain,
+      sender: CALL_CHAIN.try_with(|call_chain| call_chain.last().copied()).ok().flatten(),
+    }
+  }
+}
+
+enum Received<C>
+  where
+    C: Component,
+{
+  Message(AnyComponentMessage<C>),
+  Closed,
+  Aborted,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MailboxState {
+  Running,
+  Stopped,
+  Aborted,
+}
+
+/// Messages of a running component, handed to its [`ComponentRunner`].
+pub struct ComponentMailbox<C>
+  where
+    C: Component,
+{
+  receiver: MailboxReceiver<AnyComponentMessage<C>>,
+  signal: watch::Receiver<ComponentSignal>,
+  control: Arc<ComponentControl<C>>,
+  context: Context<C>,
+  state: MailboxState,
+}
+
+impl<C> ComponentMailbox<C>
+  where
+    C: Component,
+{
+  pub fn id(&self) -> ActorId {
+    self.context.id()
+  }
+
+  pub fn context(&mut self) -> &mut Context<C> {
+    &mut self.context
+  }
+
+  /// Whether the component was aborted, its `stopping` hook is then skipped.
+  pub fn is_aborted(&self) -> bool {
+    self.state == MailboxState::Aborted || *self.signal.borrow() == ComponentSignal::Abort
+  }
+
+  /// Receives the next message, skipping messages whose deadline passed or whose caller went away.
+  /// Returns `None` once the component stops.
+  pub async fn recv(&mut self) -> Option<AnyComponentMessage<C>> {
+    if self.state != MailboxState::Running {
+      return None;
+    }
+
+    match self.receive().await {
+      Received::Message(message) => Some(message),
+      Received::Closed => {
+        self.state = MailboxState::Stopped;
+        None
+      }
+      Received::Aborted => {
+        self.state = MailboxState::Aborted;
+        None
+      }
+    }
+  }
+
+  /// Handles `message` with exclusive access to `component`. An abort cancels the handler and
+  /// rejects the message with [`DispatchError::MailboxClosed`].
+  pub async fn dispatch(&mut self, component: &mut C, message: AnyComponentMessage<C>) -> Result<(), DispatchError> {
+    let call_chain = message.call_chain(self.id());
+
+    let result = tokio::select! {
+      biased;
+      _ = self.control.aborted() => {
+        self.state = MailboxState::Aborted;
+        Err(DispatchError::MailboxClosed)
+      }
+      result = CALL_CHAIN.scope(call_chain, message.dispatch(component, &mut self.context)) => result,
+    };
+
+    self.stop_after(&result);
+    result
+  }
+
+  /// Handles the shared `message` with shared access to `component`, so several readers can run
+  /// concurrently. Pass the result to [`Self::reader_finished`]. An abort cancels the handler and
+  /// rejects the message with [`DispatchError::MailboxClosed`]. Panics if the message is not shared.
+  pub fn dispatch_shared<'a>(
+    &self,
+    component: &'a C,
+    message: AnyComponentMessage<C>,
+  ) -> impl Future<Output=Result<(), DispatchError>> + 'a {
+    let control = self.control.clone();
+    let dispatch = CALL_CHAIN.scope(message.call_chain(self.id()), message.dispatch_shared(component));
+
+    async move {
+      tokio::select! {
+        biased;
+        _ = control.aborted() => Err(DispatchError::MailboxClosed),
+        result = dispatch => result,
+      }
+    }
+  }
+
+  /// Applies [`PanicPolicy::Stop`] to the result of a reader started with [`Self::dispatch_shared`].
+  pub fn reader_finished(&mut self, result: &Result<(), DispatchError>) {
+    self.stop_after(result)
+  }
+
+  async fn receive(&mut self) -> Received<C> {
+    loop {
+      let message = tokio::select! {
+        biased;
+        _ = self.signal.changed() => match *self.signal.borrow_and_update() {
+          ComponentSignal::Running => continue,
+          ComponentSignal::Stop => {
+            self.receiver.close();
+            continue;
+          }
+          ComponentSignal::Abort => return Received::Aborted,
+        },
+        message = self.receiver.recv() => match message {
+          Some(message) => message,
+          None => return Received::Closed,
+        },
+      };
+
+      if message.is_expired() {
+        message.reject(DispatchError::Timeout);
+        continue;
+      }
+      if message.is_cancelled() {
+        message.reject(DispatchError::Cancelled);
+        continue;
+      }
+
+      return Received::Message(message);
+    }
+  }
+
+  /// Applies [`PanicPolicy::Stop`]: queued messages are rejected and no further ones received.
+  fn stop_after(&mut self, result: &Result<(), DispatchError>) {
+    if result.is_err() && C::PANIC_POLICY == PanicPolicy::Stop && self.state == MailboxState::Running {
+      self.receiver.close();
+      self.state = MailboxState::Stopped;
+    }
+  }
+}
+
+impl<C> Debug for ComponentMailbox<C>
+  where
+    C: Component,
+{
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("ComponentMailbox")
+      .field("receiver", &self.receiver)
+      .field("state", &self.state)
+      .finish()
+  }
+}
+
+/// Drives a component by receiving messages from its mailbox and dispatching them. The runner is
+/// chosen with [`Component::Runner`]; `started` has been called before [`ComponentRunner::run`]
+/// and the stop hooks run after it returned.
+pub trait ComponentRunner<C>
+  where
+    C: Component,
+{
+  /// Handles messages until [`ComponentMailbox::recv`] returns `None`.
+  fn run<'a>(component: &'a mut C, mailbox: &'a mut ComponentMailbox<C>) -> ModeFuture<'a, C, ()>;
+}
+
+async fn run_component<C>(mut component: C, mut mailbox: ComponentMailbox<C>)
+  where
+    C: Component,
+{
+  let control = mailbox.control.clone();
+  let _termination = TerminationGuard(&control);
+
+  component.started().await;
+
+  C::Runner::run(&mut component, &mut mailbox).await;
+
+  let aborted = mailbox.is_aborted();
+  if !aborted {
+    component.stopping().await;
+  }
+  mailbox.context.children.shutdown().await;
+
+  match control.close_take() {
+    Some(take) => take(component),
+    None if !aborted => component.stopped().await,
+    None => {}
+  }
+}
+
+/// Handles one message at a time, reader messages of `#[actor(concurrent_reads)]` components concurrently.
+pub struct DefaultComponentRunner<C>(PhantomData<fn() -> C>)
+  where
+    C: Component;
+
+impl<C> ComponentRunner<C> for DefaultComponentRunner<C>
+  where
+    C: Component,
+{
+  fn run<'a>(component: &'a mut C, mailbox: &'a mut ComponentMailbox<C>) -> ModeFuture<'a, C, ()> {
+    <C::Mode as ExecutionMode<C>>::run_default(component, mailbox)
+  }
+}
+
+impl<C> DefaultComponentRunner<C>
+  where
+    C: Component,
+{
+  async fn run_messages(component: &mut C, mailbox: &mut ComponentMailbox<C>) {
+    let mut pending = None;
+
+    loop {
+      let message = match pending.take() {
+        Some(message) => message,
+        None => match mailbox.recv().await {
+          Some(message) => message,
+          None => break,
+        },
+      };
+
+      if message.is_shared() {
+        pending = Self::dispatch_readers(component, message, mailbox).await;
+      } else {
+        let _ = mailbox.dispatch(component, message).await;
+      }
+    }
+  }
+
+  /// Handles `first` and all reader messages following it concurrently. The first message that
+  /// needs exclusive access waits until every running reader has finished and is returned.
+  async fn dispatch_readers(
+    component: &mut C,
+    first: AnyComponentMessage<C>,
+    mailbox: &mut ComponentMailbox<C>,
+  ) -> Option<AnyComponentMessage<C>> {
+    let mut readers = FuturesUnordered::new();
+    let mut next = None;
+    let mut receiving = true;
+
+    let control = mailbox.control.clone();
+    readers.push(mailbox.dispatch_shared(component, first));
+
+    while !readers.is_empty() {
+      tokio::select! {
+        biased;
+        _ = control.aborted() => {
+          mailbox.state = MailboxState::Aborted;
+          return None;
+        }
+        Some(result) = readers.next() => {
+          mailbox.reader_finished(&result);
+          receiving &= mailbox.state == MailboxState::Running;
+        }
+        received = mailbox.recv(), if receiving => match received {
+          Some(message) if message.is_shared() => readers.push(mailbox.dispatch_shared(component, message)),
+          Some(message) => {
+            next = Some(message);
+            receiving = false;
+          }
+          None => receiving = false,
+        },
+      }
+    }
+
+    if mailbox.state == MailboxState::Running {
+      next
+    } else {
+      None
+    }
+  }
+}
+
+pub trait EnsureNotDroppedForDuration {
   fn ensure_not_dropped_for_duration(&self, duration: Duration) -> Pin<Box<dyn Fn() + Send + Sync>>;
 }
 
@@ -420,4 +2283,801 @@ fn ensure_not_dropped_for_duration(&self, duration: Duration) -> Pin<Box<dyn Fn(
     handle.abort();
   })
 }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use async_actor_proc::{actor, Component};
+  use std::cell::RefCell;
+  use std::rc::Rc;
+  use tokio::sync::Barrier;
+
+  #[derive(Component)]
+  pub struct Counter {
+    count: u32,
+  }
+
+  #[actor]
+  impl Counter {
+    pub async fn increment(&mut self) -> u32 {
+      self.count += 1;
+      self.count
+    }
+
+    pub async fn sleep(&mut self, duration: Duration) {
+      tokio::time::sleep(duration).await
+    }
+
+    pub async fn fail(&mut self) {
+      self.count += 1;
+      panic!("counter failed");
+    }
+
+    pub async fn wait_until_cancelled(&mut self, cancelled: oneshot::Sender<()>) {
+      cancellation().cancelled().await;
+      let _ = cancelled.send(());
+    }
+  }
+
+  #[derive(Component)]
+  pub struct Clock {
+    ticks: Arc<std::sync::Mutex<Vec<u32>>>,
+    timers: Vec<TimerHandle>,
+  }
+
+  #[actor]
+  impl Clock {
+    pub async fn schedule(&mut self, ctx: &mut Context<Self>, delay: Duration, value: u32) {
+      self.timers.push(ctx.run_later(delay, ClockTickData::new(value)));
+    }
+
+    pub async fn repeat(&mut self, ctx: &mut Context<Self>, period: Duration, value: u32) {
+      self.timers.push(ctx.run_interval(period, ClockTickData::new(value)));
+    }
+
+    pub async fn cancel_timers(&mut self) {
+      self.timers.iter().for_each(TimerHandle::cancel);
+    }
+
+    pub async fn timers_finished(&mut self) -> bool {
+      self.timers.iter().all(TimerHandle::is_finished)
+    }
+
+    pub async fn tick(&mut self, value: u32) {
+      self.ticks.lock().unwrap().push(value);
+    }
+  }
+
+  fn clock() -> (ClockHandle, Arc<std::sync::Mutex<Vec<u32>>>) {
+    let ticks = Arc::new(std::sync::Mutex::new(vec![]));
+    (Clock { ticks: ticks.clone(), timers: vec![] }.start(), ticks)
+  }
+
+  #[derive(Component)]
+  pub struct Numbers;
+
+  #[actor]
+  impl Numbers {
+    pub async fn count_to(&mut self, limit: u32, items: ResponseSender<u32>) {
+      for item in 1..=limit {
+        if items.send(item).await.is_err() {
+          return;
+        }
+      }
+    }
+
+    pub async fn count_then_fail(&mut self, items: ResponseSender<u32>) {
+      let _ = items.send(1).await;
+      panic!("counting failed");
+    }
+
+    pub async fn count_until_dropped(&mut self, items: ResponseSender<u32>, dropped: oneshot::Sender<DispatchError>) {
+      for item in 1.. {
+        if let Err(error) = items.send(item).await {
+          cancellation().cancelled().await;
+          let _ = dropped.send(error);
+          return;
+        }
+      }
+    }
+  }
+
+  async fn collect(stream: &mut ResponseStream<u32>) -> Vec<u32> {
+    let mut items = vec![];
+    while let Some(item) = stream.next().await {
+      items.push(item);
+    }
+    items
+  }
+
+  #[derive(Component)]
+  pub struct Voter {
+    answer: Option<u32>,
+    delay: Duration,
+  }
+
+  #[actor]
+  impl Voter {
+    pub async fn vote(&mut self) -> u32 {
+      tokio::time::sleep(self.delay).await;
+      self.answer.expect("no answer")
+    }
+  }
+
+  fn voter(answer: Option<u32>, delay: u64) -> MessageSender<VoterVoteData, u32> {
+    Voter { answer, delay: Duration::from_millis(delay) }.start().inner.make_sender()
+  }
+
+  async fn closed_voter() -> MessageSender<VoterVoteData, u32> {
+    let voter = Voter { answer: Some(0), delay: Duration::ZERO }.start();
+    let sender = voter.inner.make_sender();
+    voter.stop();
+    voter.terminated().await;
+    sender
+  }
+
+  #[derive(Component)]
+  #[component(mailbox = 1, overflow = "drop_oldest")]
+  pub struct Bounded;
+
+  #[actor]
+  impl Bounded {
+    pub async fn sleep(&mut self, duration: Duration) {
+      tokio::time::sleep(duration).await
+    }
+
+    pub async fn echo(&mut self, value: u32) -> u32 {
+      value
+    }
+  }
+
+  #[derive(Component)]
+  pub struct Journal {
+    entries: Vec<u32>,
+  }
+
+  #[actor]
+  impl Journal {
+    pub async fn sleep(&mut self, duration: Duration) {
+      tokio::time::sleep(duration).await
+    }
+
+    #[priority(low)]
+    pub async fn low(&mut self, entry: u32) {
+      self.entries.push(entry)
+    }
+
+    pub async fn normal(&mut self, entry: u32) {
+      self.entries.push(entry)
+    }
+
+    #[priority(high)]
+    pub async fn high(&mut self, entry: u32) {
+      self.entries.push(entry)
+    }
+  }
+
+  #[derive(Component)]
+  #[component(on_panic = "stop")]
+  pub struct Library {
+    value: u32,
+  }
+
+  #[actor(concurrent_reads)]
+  impl Library {
+    pub async fn meet(&self, barrier: Arc<Barrier>) -> u32 {
+      barrier.wait().await;
+      self.value
+    }
+
+    pub async fn hold(&self, release: oneshot::Receiver<()>) -> u32 {
+      let _ = release.await;
+      self.value
+    }
+
+    pub async fn fail(&self) {
+      panic!("failed")
+    }
+
+    pub async fn write(&mut self, value: u32) {
+      self.value = value
+    }
+  }
+
+  #[derive(Component)]
+  pub struct Relay;
+
+  #[actor]
+  impl Relay {
+    pub async fn value(&mut self) -> u32 {
+      1
+    }
+
+    pub async fn own_id(&mut self, ctx: &mut Context<Self>) -> ActorId {
+      ctx.id()
+    }
+
+    pub async fn forward(&mut self, to: RelayHandle) -> Result<u32, DispatchError> {
+      to.try_value().await
+    }
+
+    pub async fn bounce(&mut self, via: RelayHandle, back: RelayHandle) -> Result<Result<u32, DispatchError>, DispatchError> {
+      via.try_forward(back).await
+    }
+  }
+
+  #[derive(Component)]
+  #[component(runner = "PairedReaders")]
+  pub struct Shelf {
+    value: u32,
+  }
+
+  #[actor(concurrent_reads)]
+  impl Shelf {
+    pub async fn meet(&self, barrier: Arc<Barrier>) -> u32 {
+      barrier.wait().await;
+      self.value
+    }
+  }
+
+  /// Starts the first two messages together, then handles the rest one at a time.
+  pub struct PairedReaders;
+
+  impl ComponentRunner<Shelf> for PairedReaders {
+    fn run<'a>(component: &'a mut Shelf, mailbox: &'a mut ComponentMailbox<Shelf>) -> ModeFuture<'a, Shelf, ()> {
+      Box::pin(async move {
+        {
+          let mut readers = FuturesUnordered::new();
+          while readers.len() < 2 {
+            let Some(message) = mailbox.recv().await else { return };
+            assert!(message.is_shared());
+            assert_eq!(message.kind(), TypeId::of::<ShelfMeetData>());
+            assert!(message.name().ends_with("ShelfMeetData"));
+            readers.push(mailbox.dispatch_shared(component, message));
+          }
+          while let Some(result) = readers.next().await {
+            mailbox.reader_finished(&result);
+          }
+        }
+
+        while let Some(message) = mailbox.recv().await {
+          let _ = mailbox.dispatch(component, message).await;
+        }
+      })
+    }
+  }
+
+  #[derive(Component)]
+  #[component(lifecycle)]
+  pub struct Booted {
+    hooks: Arc<std::sync::Mutex<Vec<&'static str>>>,
+  }
+
+  impl ComponentLifecycle for Booted {
+    async fn on_started(&mut self) {
+      self.hooks.lock().unwrap().push("started");
+    }
+
+    async fn on_stopping(&mut self) {
+      self.hooks.lock().unwrap().push("stopping");
+    }
+
+    async fn on_stopped(self) {
+      self.hooks.lock().unwrap().push("stopped");
+    }
+  }
+
+  #[actor]
+  impl Booted {
+    pub async fn is_booted(&mut self) -> bool {
+      self.hooks.lock().unwrap().contains(&"started")
+    }
+  }
+
+  async fn booted() -> (BootedHandle, Arc<std::sync::Mutex<Vec<&'static str>>>) {
+    let hooks = Arc::new(std::sync::Mutex::new(vec![]));
+    let booted = Booted { hooks: hooks.clone() }.start();
+    assert_eq!(booted.try_is_booted().await, Ok(true));
+    (booted, hooks)
+  }
+
+  #[derive(Component)]
+  #[component(local, lifecycle)]
+  pub struct Tally {
+    seen: Rc<RefCell<Vec<u32>>>,
+    thread: std::thread::ThreadId,
+  }
+
+  impl ComponentLifecycle for Tally {
+    async fn on_started(&mut self) {
+      self.seen.borrow_mut().push(0);
+    }
+  }
+
+  #[actor]
+  impl Tally {
+    pub async fn record(&mut self, value: u32) -> usize {
+      tokio::task::yield_now().await;
+      self.seen.borrow_mut().push(value);
+      self.seen.borrow().len()
+    }
+
+    pub async fn is_on_own_thread(&mut self) -> bool {
+      std::thread::current().id() == self.thread
+    }
+  }
+
+  #[tokio::test]
+  async fn evicted_messages_are_answered_with_dropped() {
+    let bounded = Bounded.start();
+    let sleeping = tokio::spawn({
+      let bounded = bounded.clone();
+      async move { bounded.try_sleep(Duration::from_millis(50)).await }
+    });
+    tokio::time::sleep(Duration::from_millis(10)).await;
+
+    let (first, second) = tokio::join!(bounded.try_echo(1), bounded.try_echo(2));
+    assert_eq!(sleeping.await.unwrap(), Ok(()));
+    assert_eq!(first, Err(DispatchError::Dropped));
+    assert_eq!(second, Ok(2));
+  }
+
+  #[tokio::test]
+  async fn queued_messages_are_handled_by_priority() {
+    let journal = Journal { entries: vec![] }.start();
+    journal.inner.dispatch_sync_nowait(JournalSleepData::new(Duration::from_millis(20)));
+    tokio::time::sleep(Duration::from_millis(5)).await;
+
+    journal.inner.dispatch_sync_nowait(JournalLowData::new(1));
+    journal.inner.dispatch_sync_nowait(JournalNormalData::new(2));
+    journal.inner.dispatch_sync_nowait(JournalHighData::new(3));
+    journal.inner.dispatch_sync_nowait(JournalLowData::new(4));
+    journal.inner.dispatch_sync_nowait(JournalHighData::new(5));
+    journal.inner.dispatch_sync_nowait(JournalNormalData::new(6));
+
+    assert_eq!(journal.stop_and_take().await.unwrap().entries, vec![3, 5, 2, 6, 1, 4]);
+  }
+
+  #[tokio::test]
+  async fn abort_right_after_start_terminates() {
+    let counter = Counter { count: 0 }.start();
+    counter.abort();
+
+    tokio::time::timeout(Duration::from_secs(1), counter.terminated()).await.unwrap();
+  }
+
+  #[tokio::test]
+  async fn stop_right_after_start_terminates() {
+    let counter = Counter { count: 0 }.start();
+    counter.stop();
+
+    tokio::time::timeout(Duration::from_secs(1), counter.terminated()).await.unwrap();
+  }
+
+  #[tokio::test]
+  async fn take_right_after_start_returns_component() {
+    let counter = Counter { count: 3 }.start();
+    let taken = tokio::time::timeout(Duration::from_secs(1), counter.stop_and_take()).await.unwrap();
+
+    assert_eq!(taken.map(|counter| counter.count), Some(3));
+  }
+
+  #[tokio::test]
+  async fn take_handles_queued_messages_first() {
+    let counter = Counter { count: 0 }.start();
+    let increments = (0..3).map(|_| counter.try_increment()).collect::<FuturesUnordered<_>>();
+    let taken = counter.stop_and_take();
+
+    let (answers, taken) = tokio::join!(increments.collect::<Vec<_>>(), taken);
+    assert!(answers.iter().all(Result::is_ok));
+    assert_eq!(taken.map(|counter| counter.count), Some(3));
+  }
+
+  #[tokio::test]
+  async fn shutdown_stops_idle_actors() {
+    let system = ActorSystem::new().with_shutdown_timeout(Duration::from_secs(5));
+    let _counter = system.spawn(Counter { count: 0 });
+
+    let started = Instant::now();
+    let report = system.shutdown().await;
+
+    assert_eq!((report.stopped.len(), report.aborted.len()), (1, 0));
+    assert!(started.elapsed() < Duration::from_secs(1));
+  }
+
+  #[tokio::test]
+  async fn shutdown_aborts_busy_actors() {
+    let system = ActorSystem::new().with_shutdown_timeout(Duration::from_millis(50));
+    let idle = system.spawn(Counter { count: 0 });
+    let busy = system.spawn(Counter { count: 0 });
+    let sleeping = tokio::spawn(async move { busy.try_sleep(Duration::from_secs(60)).await });
+    tokio::task::yield_now().await;
+
+    let report = system.shutdown().await;
+
+    assert_eq!(report.stopped.iter().map(|actor| actor.id).collect::<Vec<_>>(), vec![idle.inner.id()]);
+    assert_eq!(report.aborted.len(), 1);
+    assert_eq!(sleeping.await.unwrap(), Err(DispatchError::MailboxClosed));
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn handlers_exceeding_the_timeout_fail_with_timeout() {
+    let counter = Counter { count: 0 }.start();
+
+    let answer = counter.inner.try_dispatch_timeout(CounterSleepData::new(Duration::from_secs(60)), Duration::from_millis(20)).await;
+    assert_eq!(answer, Err(DispatchError::Timeout));
+
+    let answer = counter.clone().with_timeout(Duration::from_millis(20)).try_sleep(Duration::from_secs(60)).await;
+    assert_eq!(answer, Err(DispatchError::Timeout));
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn messages_expiring_in_the_queue_are_not_handled() {
+    let counter = Counter { count: 0 }.start();
+    let sleeping = tokio::spawn({
+      let counter = counter.clone();
+      async move { counter.try_sleep(Duration::from_millis(50)).await }
+    });
+    tokio::task::yield_now().await;
+
+    let answer = counter.inner.try_dispatch_timeout(CounterIncrementData::new(), Duration::from_millis(10)).await;
+    assert_eq!(answer, Err(DispatchError::Timeout));
+
+    assert_eq!(sleeping.await.unwrap(), Ok(()));
+    assert_eq!(counter.try_increment().await, Ok(1));
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn queued_messages_are_skipped_once_their_caller_gave_up() {
+    let counter = Counter { count: 0 }.start();
+    let sleeping = tokio::spawn({
+      let counter = counter.clone();
+      async move { counter.try_sleep(Duration::from_millis(50)).await }
+    });
+    tokio::task::yield_now().await;
+
+    assert!(counter.try_increment().now_or_never().is_none());
+
+    assert_eq!(sleeping.await.unwrap(), Ok(()));
+    assert_eq!(counter.try_increment().await, Ok(1));
+  }
+
+  #[tokio::test]
+  async fn running_handlers_see_the_caller_giving_up() {
+    let counter = Counter { count: 0 }.start();
+    let (cancelled, was_cancelled) = oneshot::channel();
+    let waiting = tokio::spawn({
+      let counter = counter.clone();
+      async move { counter.try_wait_until_cancelled(cancelled).await }
+    });
+    tokio::task::yield_now().await;
+
+    waiting.abort();
+
+    assert_eq!(tokio::time::timeout(Duration::from_secs(1), was_cancelled).await, Ok(Ok(())));
+    assert_eq!(counter.try_increment().await, Ok(1));
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn timers_send_their_message() {
+    let (clock, ticks) = clock();
+    clock.try_schedule(Duration::from_millis(10), 1).await.unwrap();
+    clock.try_repeat(Duration::from_millis(20), 2).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    assert_eq!(*ticks.lock().unwrap(), vec![1, 2, 2]);
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn cancelled_timers_do_not_fire() {
+    let (clock, ticks) = clock();
+    clock.try_schedule(Duration::from_millis(10), 1).await.unwrap();
+    clock.try_repeat(Duration::from_millis(10), 2).await.unwrap();
+    clock.try_cancel_timers().await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    assert!(ticks.lock().unwrap().is_empty());
+    assert_eq!(clock.try_timers_finished().await, Ok(true));
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn timers_stop_with_the_component() {
+    let (clock, ticks) = clock();
+    clock.try_repeat(Duration::from_millis(10), 1).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(25)).await;
+
+    let Some(clock) = clock.stop_and_take().await else { panic!("clock was not taken") };
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    assert!(clock.timers.iter().all(TimerHandle::is_finished));
+    assert_eq!(*ticks.lock().unwrap(), vec![1, 1]);
+  }
+
+  #[tokio::test]
+  async fn streams_yield_items_in_order_and_end_cleanly() {
+    let numbers = Numbers.start();
+    let mut stream = numbers.count_to(100).await;
+
+    assert_eq!(collect(&mut stream).await, (1..=100).collect::<Vec<_>>());
+    assert_eq!(stream.error(), None);
+    assert_eq!(stream.next().await, None);
+  }
+
+  #[tokio::test]
+  async fn streams_report_why_the_dispatch_failed() {
+    let numbers = Numbers.start();
+    let mut failing = numbers.count_then_fail().await;
+
+    assert_eq!(collect(&mut failing).await, vec![1]);
+    assert_eq!(failing.error(), Some(&DispatchError::HandlerPanicked("counting failed".to_string())));
+
+    numbers.stop();
+    numbers.terminated().await;
+    let mut closed = numbers.count_to(3).await;
+
+    assert_eq!(collect(&mut closed).await, Vec::<u32>::new());
+    assert_eq!(closed.error(), Some(&DispatchError::MailboxClosed));
+  }
+
+  #[tokio::test]
+  async fn dropping_the_stream_cancels_the_handler() {
+    let numbers = Numbers.start();
+    let (dropped, was_dropped) = oneshot::channel();
+    let mut stream = numbers.count_until_dropped(dropped).await;
+    assert_eq!(stream.next().await, Some(1));
+
+    drop(stream);
+
+    assert_eq!(tokio::time::timeout(Duration::from_secs(1), was_dropped).await, Ok(Ok(DispatchError::Cancelled)));
+  }
+
+  #[tokio::test]
+  async fn empty_groups_answer_nothing() {
+    let group = RecipientGroup::<VoterVoteData, u32>::new();
+
+    assert!(group.broadcast_nowait(VoterVoteData::new()).is_empty());
+    assert!(group.broadcast(VoterVoteData::new()).await.is_empty());
+    assert_eq!(group.gather(VoterVoteData::new(), 0).await, Ok(vec![]));
+    assert_eq!(group.gather(VoterVoteData::new(), 1).await, Err(vec![]));
+    assert_eq!(group.first_answer(VoterVoteData::new()).await, Err(vec![]));
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn gather_tolerates_failures_while_the_quorum_is_reachable() {
+    let group = [voter(Some(1), 0), voter(None, 0), voter(Some(2), 10)].into_iter().collect::<RecipientGroup<_, _>>();
+    let failed = DispatchError::HandlerPanicked("no answer".to_string());
+
+    assert_eq!(group.broadcast(VoterVoteData::new()).await, vec![Ok(1), Err(failed.clone()), Ok(2)]);
+    assert_eq!(group.gather(VoterVoteData::new(), 2).await, Ok(vec![1, 2]));
+    assert_eq!(group.gather(VoterVoteData::new(), 3).await, Err(vec![failed]));
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn first_answer_skips_closed_members() {
+    let mut group = RecipientGroup::new();
+    group.push(closed_voter().await);
+    group.push(voter(Some(1), 50));
+    group.push(voter(Some(2), 10));
+
+    assert_eq!(group.first_answer(VoterVoteData::new()).await, Ok(2));
+
+    let group = [closed_voter().await, voter(None, 0)].into_iter().collect::<RecipientGroup<_, _>>();
+    let errors = group.first_answer(VoterVoteData::new()).await.unwrap_err();
+    assert_eq!(errors.len(), 2);
+    assert!(errors.contains(&DispatchError::MailboxClosed));
+  }
+
+  #[tokio::test]
+  async fn components_resume_after_a_handler_panicked() {
+    let counter = Counter { count: 0 }.start();
+
+    assert_eq!(counter.try_fail().await, Err(DispatchError::HandlerPanicked("counter failed".to_string())));
+    assert_eq!(counter.try_increment().await, Ok(2));
+  }
+
+  #[tokio::test]
+  async fn system_runs_components_again_after_shutdown() {
+    let system = ActorSystem::new();
+    let first = system.spawn(Counter { count: 0 });
+    system.shutdown().await;
+    assert!(first.inner.is_terminated());
+
+    let second = system.spawn(Counter { count: 0 });
+    assert_eq!(second.try_increment().await, Ok(1));
+    assert_eq!(system.len(), 1);
+  }
+
+  #[tokio::test]
+  async fn readers_run_concurrently() {
+    let library = Library { value: 1 }.start();
+    let barrier = Arc::new(Barrier::new(2));
+
+    let met = tokio::time::timeout(
+      Duration::from_secs(1),
+      futures::future::join(library.try_meet(barrier.clone()), library.try_meet(barrier)),
+    ).await;
+    assert_eq!(met, Ok((Ok(1), Ok(1))));
+  }
+
+  #[tokio::test]
+  async fn writer_waits_for_running_readers() {
+    let library = Library { value: 0 }.start();
+    let (release, held) = oneshot::channel();
+
+    let hold = tokio::spawn({
+      let library = library.clone();
+      async move { library.try_hold(held).await }
+    });
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    let write = tokio::spawn({
+      let library = library.clone();
+      async move { library.try_write(1).await }
+    });
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    assert!(!write.is_finished());
+
+    release.send(()).unwrap();
+    assert_eq!(hold.await.unwrap(), Ok(0));
+    assert_eq!(write.await.unwrap(), Ok(()));
+    assert_eq!(library.try_meet(Arc::new(Barrier::new(1))).await, Ok(1));
+  }
+
+  #[tokio::test]
+  async fn abort_interrupts_running_readers() {
+    let library = Library { value: 0 }.start();
+    let (_release, held) = oneshot::channel();
+
+    let hold = tokio::spawn({
+      let library = library.clone();
+      async move { library.try_hold(held).await }
+    });
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    library.abort();
+
+    assert!(tokio::time::timeout(Duration::from_secs(1), library.terminated()).await.is_ok());
+    assert_eq!(hold.await.unwrap(), Err(DispatchError::MailboxClosed));
+  }
+
+  #[tokio::test]
+  async fn panicking_reader_stops_the_component_after_running_readers() {
+    let library = Library { value: 0 }.start();
+    let (release, held) = oneshot::channel();
+
+    let (hold, fail, write, _) = tokio::join!(
+      library.try_hold(held),
+      library.try_fail(),
+      library.try_write(1),
+      async {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        release.send(()).unwrap();
+      },
+    );
+    assert_eq!(hold, Ok(0));
+    assert_eq!(fail, Err(DispatchError::HandlerPanicked("failed".to_string())));
+    assert_eq!(write, Err(DispatchError::MailboxClosed));
+
+    assert!(tokio::time::timeout(Duration::from_secs(1), library.terminated()).await.is_ok());
+    assert_eq!(library.try_write(2).await, Err(DispatchError::MailboxClosed));
+  }
+
+  #[tokio::test]
+  async fn calling_back_into_a_waiting_component_is_a_deadlock() {
+    let a = Relay.start();
+    let b = Relay.start();
+
+    let bounced = tokio::time::timeout(Duration::from_secs(1), a.try_bounce(b.clone(), a.clone())).await;
+    assert_eq!(bounced, Ok(Ok(Ok(Err(DispatchError::Deadlock)))));
+    assert_eq!(b.try_forward(a).await, Ok(Ok(1)));
+  }
+
+  #[tokio::test]
+  async fn calling_itself_is_a_deadlock() {
+    let a = Relay.start();
+
+    let forwarded = tokio::time::timeout(Duration::from_secs(1), a.try_forward(a.clone())).await;
+    assert_eq!(forwarded, Ok(Ok(Err(DispatchError::Deadlock))));
+  }
+
+  #[tokio::test]
+  async fn attached_stream_does_not_keep_the_component_alive() {
+    let counter = Counter { count: 0 }.start();
+    let (items, receiver) = mpsc::channel(1);
+    let attachment = counter.inner.attach_receiver(receiver);
+    let weak = counter.downgrade();
+    drop(counter);
+
+    assert!(tokio::time::timeout(Duration::from_secs(1), weak.inner.terminated()).await.is_ok());
+    items.send(CounterIncrementData::new()).await.unwrap();
+    assert_eq!(attachment.finished().await, StreamEnd::Closed);
+  }
+
+  #[tokio::test]
+  async fn only_the_unique_handle_takes_the_component() {
+    let counter = Counter { count: 0 }.start();
+    let weak = counter.downgrade();
+    assert!(counter.clone().to_unique().is_err());
+
+    let Ok(unique) = counter.to_unique() else { panic!("counter has another strong handle") };
+    let upgraded = weak.upgrade().unwrap();
+    assert_eq!(upgraded.try_increment().await, Ok(1));
+    assert!(upgraded.stop_and_take().await.is_none());
+
+    assert_eq!(unique.stop_and_take().await.map(|counter| counter.count), Some(1));
+  }
+
+  #[tokio::test]
+  async fn context_parameter_is_supplied_by_the_runner() {
+    let relay = Relay.start();
+    assert_eq!(relay.try_own_id().await, Ok(relay.inner.id()));
+  }
+
+  #[tokio::test]
+  async fn custom_runners_can_run_readers_concurrently() {
+    let shelf = Shelf { value: 1 }.start();
+    let barrier = Arc::new(Barrier::new(2));
+
+    let met = tokio::time::timeout(
+      Duration::from_secs(1),
+      futures::future::join(shelf.try_meet(barrier.clone()), shelf.try_meet(barrier)),
+    ).await;
+    assert_eq!(met, Ok((Ok(1), Ok(1))));
+    assert_eq!(shelf.try_meet(Arc::new(Barrier::new(1))).await, Ok(1));
+  }
+
+  #[tokio::test]
+  async fn lifecycle_hooks_are_forwarded() {
+    let (booted, hooks) = booted().await;
+    booted.stop();
+    booted.terminated().await;
+
+    assert_eq!(*hooks.lock().unwrap(), vec!["started", "stopping", "stopped"]);
+  }
+
+  #[tokio::test]
+  async fn aborted_components_skip_the_stop_hooks() {
+    let (booted, hooks) = booted().await;
+    booted.abort();
+    booted.terminated().await;
+
+    assert_eq!(*hooks.lock().unwrap(), vec!["started"]);
+  }
+
+  #[tokio::test]
+  async fn taken_components_are_not_stopped() {
+    let (booted, hooks) = booted().await;
+    let Some(_booted) = booted.stop_and_take().await else { panic!("component was not taken") };
+
+    assert_eq!(*hooks.lock().unwrap(), vec!["started", "stopping"]);
+  }
+
+  #[tokio::test(flavor = "multi_thread")]
+  async fn local_components_stay_on_their_local_set() {
+    let local_set = LocalSet::new();
+    let seen = Rc::new(RefCell::new(vec![]));
+    let tally = Tally { seen: seen.clone(), thread: std::thread::current().id() }.start_local(&local_set);
+
+    let answers = tokio::spawn(async move { (tally.try_record(1).await, tally.try_is_on_own_thread().await) });
+    let answers = local_set.run_until(answers).await.unwrap();
+
+    assert_eq!(answers, (Ok(2), Ok(true)));
+    assert_eq!(*seen.borrow(), vec![0, 1]);
+  }
+
+  #[tokio::test]
+  async fn system_shuts_down_local_components() {
+    let local_set = LocalSet::new();
+    let system = ActorSystem::new();
+    let seen = Rc::new(RefCell::new(vec![]));
+    let tally = system.spawn_local(Tally { seen: seen.clone(), thread: std::thread::current().id() }, &local_set);
+
+    let report = local_set.run_until(async {
+      assert_eq!(tally.try_record(1).await, Ok(2));
+      system.shutdown().await
+    }).await;
+
+    assert_eq!((report.stopped.len(), report.aborted.len()), (1, 0));
+    assert_eq!(tally.try_record(2).await, Err(DispatchError::MailboxClosed));
+  }
+}
@@ -0,0 +1,665 @@
+use std::any::TypeId;
+use std::collections::VecDeque;
+use std::fmt::{Debug, Formatter};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+
+pub trait MailboxItem {
+    fn kind(&self) -> TypeId;
+
+    fn priority(&self) -> Priority;
+}
+
+/// Mailbox lane of a value. Higher lanes are received first, each lane is FIFO.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    Low,
+    Normal,
+    High,
+}
+
+impl Priority {
+    const LANES: usize = 3;
+
+    fn lane(self) -> usize {
+        self as usize
+    }
+}
+
+/// What a full mailbox does with a new value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Wait for free space. Senders that cannot wait get [`TrySendError::Full`].
+    Block,
+    /// Refuse the new value with [`TrySendError::Full`].
+    Reject,
+    /// Accept and evict the new value.
+    DropNewest,
+    /// Evict the oldest queued value of the lowest non-empty lane to make room.
+    DropOldest,
+    /// Evict all queued values of the same kind as the new one, otherwise behave like [`OverflowPolicy::DropOldest`].
+    KeepLatest,
+}
+
+struct State<T> {
+    lanes: [VecDeque<T>; Priority::LANES],
+    len: usize,
+    capacity: Option<usize>,
+    overflow: OverflowPolicy,
+    senders: usize,
+    closed: bool,
+}
+
+impl<T> State<T> {
+    fn is_closed(&self) -> bool {
+        self.closed || self.senders == 0
+    }
+
+    fn is_full(&self) -> bool {
+        self.capacity
+            .map(|capacity| self.len >= capacity)
+            .unwrap_or(false)
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        let value = self.lanes.iter_mut().rev().find_map(|lane| lane.pop_front())?;
+        self.len -= 1;
+
+        Some(value)
+    }
+
+    fn pop_oldest(&mut self) -> Option<T> {
+        let value = self.lanes.iter_mut().find_map(|lane| lane.pop_front())?;
+        self.len -= 1;
+
+        Some(value)
+    }
+
+    fn clear(&mut self) -> Vec<T> {
+        self.len = 0;
+
+        self.lanes.iter_mut().flat_map(std::mem::take).collect()
+    }
+}
+
+impl<T> State<T>
+where
+    T: MailboxItem,
+{
+    fn push(&mut self, value: T) -> Result<Vec<T>, TrySendError<T>> {
+        if self.is_closed() {
+            return Err(TrySendError::Closed(value));
+        }
+        if !self.is_full() {
+            self.push_unchecked(value);
+            return Ok(vec![]);
+        }
+
+        let evicted = match self.overflow {
+            OverflowPolicy::Block | OverflowPolicy::Reject => return Err(TrySendError::Full(value)),
+            OverflowPolicy::DropNewest => return Ok(vec![value]),
+            OverflowPolicy::DropOldest => self.pop_oldest().into_iter().collect(),
+            OverflowPolicy::KeepLatest => {
+                let kind = value.kind();
+                let mut evicted = vec![];
+                for lane in self.lanes.iter_mut() {
+                    let (same_kind, kept) = std::mem::take(lane)
+                        .into_iter()
+                        .partition::<Vec<_>, _>(|queued| queued.kind() == kind);
+                    *lane = kept.into();
+                    evicted.extend(same_kind);
+                }
+                self.len -= evicted.len();
+
+                if evicted.is_empty() {
+                    self.pop_oldest().into_iter().collect()
+                } else {
+                    evicted
+                }
+            }
+        };
+
+        self.push_unchecked(value);
+        Ok(evicted)
+    }
+
+    fn push_unchecked(&mut self, value: T) {
+        self.lanes[value.priority().lane()].push_back(value);
+        self.len += 1;
+    }
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    receive: Notify,
+    space: Notify,
+}
+
+impl<T> Shared<T>
+where
+    T: MailboxItem,
+{
+    fn try_send(&self, value: T) -> Result<Vec<T>, TrySendError<T>> {
+        let evicted = self.state.lock().unwrap().push(value)?;
+
+        self.receive.notify_one();
+        Ok(evicted)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TrySendError<T> {
+    Full(T),
+    Closed(T),
+}
+
+/// Creates a mailbox holding at most `capacity` items, unbounded for `None`. Panics if the
+/// capacity is 0, as no item could ever be sent.
+pub fn channel<T>(
+    capacity: Option<usize>,
+    overflow: OverflowPolicy,
+) -> (MailboxSender<T>, MailboxReceiver<T>) {
+    assert!(capacity != Some(0), "mailbox capacity must be at least 1");
+
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            lanes: Default::default(),
+            len: 0,
+            capacity,
+            overflow,
+            senders: 1,
+            closed: false,
+        }),
+        receive: Notify::new(),
+        space: Notify::new(),
+    });
+
+    (
+        MailboxSender {
+            shared: shared.clone(),
+        },
+        MailboxReceiver { shared },
+    )
+}
+
+pub struct MailboxSender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> MailboxSender<T>
+where
+    T: MailboxItem,
+{
+    /// Queues `value`, waiting for free space under [`OverflowPolicy::Block`].
+    /// Returns the values evicted by the overflow policy.
+    pub async fn send(&self, value: T) -> Result<Vec<T>, TrySendError<T>> {
+        let mut value = value;
+
+        loop {
+            let space = self.shared.space.notified();
+            tokio::pin!(space);
+            space.as_mut().enable();
+
+            match self.try_send(value) {
+                Err(TrySendError::Full(returned)) if self.overflow() == OverflowPolicy::Block => value = returned,
+                result => return result,
+            }
+
+            space.await;
+        }
+    }
+
+    pub fn blocking_send(&self, value: T) -> Result<Vec<T>, TrySendError<T>> {
+        futures::executor::block_on(self.send(value))
+    }
+
+    /// Queues `value` without waiting. Returns the values evicted by the overflow policy.
+    pub fn try_send(&self, value: T) -> Result<Vec<T>, TrySendError<T>> {
+        self.shared.try_send(value)
+    }
+
+    fn overflow(&self) -> OverflowPolicy {
+        self.shared.state.lock().unwrap().overflow
+    }
+}
+
+impl<T> MailboxSender<T> {
+    /// Identifies the mailbox for as long as it exists, shared by all its senders and the receiver.
+    pub fn id(&self) -> usize {
+        Arc::as_ptr(&self.shared) as *const () as usize
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.shared.state.lock().unwrap().is_closed()
+    }
+
+    /// Whether this is the only sender, weak senders aside.
+    pub fn is_unique(&self) -> bool {
+        self.shared.state.lock().unwrap().senders == 1
+    }
+
+    /// Runs `f` if this is the only sender. Weak senders cannot upgrade until `f` returned.
+    pub fn when_unique<R>(&self, f: impl FnOnce() -> R) -> Option<R> {
+        let state = self.shared.state.lock().unwrap();
+        (state.senders == 1).then(f)
+    }
+
+    /// Closes the mailbox from the sending side, see [`MailboxReceiver::close`].
+    pub fn close(&self) {
+        self.shared.state.lock().unwrap().closed = true;
+        self.shared.space.notify_waiters();
+        self.shared.receive.notify_one();
+    }
+
+    pub fn downgrade(&self) -> MailboxWeakSender<T> {
+        MailboxWeakSender {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Clone for MailboxSender<T> {
+    fn clone(&self) -> Self {
+        self.shared.state.lock().unwrap().senders += 1;
+
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for MailboxSender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.senders -= 1;
+
+        if state.senders == 0 {
+            drop(state);
+            self.shared.receive.notify_one();
+        }
+    }
+}
+
+impl<T> Debug for MailboxSender<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let state = self.shared.state.lock().unwrap();
+
+        f.debug_struct("MailboxSender")
+            .field("len", &state.len)
+            .field("capacity", &state.capacity)
+            .field("closed", &state.is_closed())
+            .finish()
+    }
+}
+
+/// Sender that does not keep the mailbox open.
+pub struct MailboxWeakSender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> MailboxWeakSender<T> {
+    pub fn id(&self) -> usize {
+        Arc::as_ptr(&self.shared) as *const () as usize
+    }
+
+    /// Returns a sender while the mailbox is still open.
+    pub fn upgrade(&self) -> Option<MailboxSender<T>> {
+        let mut state = self.shared.state.lock().unwrap();
+
+        if state.is_closed() {
+            return None;
+        }
+        state.senders += 1;
+
+        Some(MailboxSender {
+            shared: self.shared.clone(),
+        })
+    }
+
+    /// Closes the mailbox like [`MailboxSender::close`].
+    pub fn close(&self) {
+        self.shared.state.lock().unwrap().closed = true;
+        self.shared.space.notify_waiters();
+        self.shared.receive.notify_one();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.shared.state.lock().unwrap().is_closed()
+    }
+}
+
+impl<T> MailboxWeakSender<T>
+where
+    T: MailboxItem,
+{
+    /// Queues `value` like [`MailboxSender::try_send`] without upgrading.
+    pub fn try_send(&self, value: T) -> Result<Vec<T>, TrySendError<T>> {
+        self.shared.try_send(value)
+    }
+}
+
+impl<T> Clone for MailboxWeakSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Debug for MailboxWeakSender<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let state = self.shared.state.lock().unwrap();
+
+        f.debug_struct("MailboxWeakSender")
+            .field("closed", &state.is_closed())
+            .finish()
+    }
+}
+
+pub struct MailboxReceiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> MailboxReceiver<T> {
+    pub fn id(&self) -> usize {
+        Arc::as_ptr(&self.shared) as *const () as usize
+    }
+
+    pub async fn recv(&mut self) -> Option<T> {
+        loop {
+            {
+                let mut state = self.shared.state.lock().unwrap();
+
+                if let Some(value) = state.pop() {
+                    drop(state);
+                    self.shared.space.notify_one();
+                    return Some(value);
+                }
+                if state.is_closed() {
+                    return None;
+                }
+            }
+
+            self.shared.receive.notified().await;
+        }
+    }
+
+    /// Rejects all further sends while keeping the already queued values receivable.
+    pub fn close(&mut self) {
+        self.shared.state.lock().unwrap().closed = true;
+        self.shared.space.notify_waiters();
+    }
+}
+
+impl<T> Drop for MailboxReceiver<T> {
+    fn drop(&mut self) {
+        self.close();
+
+        let queue = self.shared.state.lock().unwrap().clear();
+        drop(queue);
+    }
+}
+
+impl<T> Debug for MailboxReceiver<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let state = self.shared.state.lock().unwrap();
+
+        f.debug_struct("MailboxReceiver")
+            .field("len", &state.len)
+            .field("capacity", &state.capacity)
+            .field("closed", &state.is_closed())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+    use std::time::Duration;
+
+    #[derive(Debug, PartialEq, Eq)]
+    struct Item {
+        value: u32,
+        kind: TypeId,
+        priority: Priority,
+    }
+
+    impl MailboxItem for Item {
+        fn kind(&self) -> TypeId {
+            self.kind
+        }
+
+        fn priority(&self) -> Priority {
+            self.priority
+        }
+    }
+
+    fn item(value: u32) -> Item {
+        Item { value, kind: TypeId::of::<u32>(), priority: Priority::Normal }
+    }
+
+    fn other_item(value: u32) -> Item {
+        Item { value, kind: TypeId::of::<u64>(), priority: Priority::Normal }
+    }
+
+    fn values(items: Vec<Item>) -> Vec<u32> {
+        items.into_iter().map(|item| item.value).collect()
+    }
+
+    fn drain(receiver: &mut MailboxReceiver<Item>) -> Vec<u32> {
+        let mut values = vec![];
+        while let Some(Some(item)) = receiver.recv().now_or_never() {
+            values.push(item.value);
+        }
+        values
+    }
+
+    #[test]
+    fn bounded_mailbox_refuses_values_beyond_capacity() {
+        let (sender, mut receiver) = channel(Some(2), OverflowPolicy::Block);
+
+        assert_eq!(sender.try_send(item(1)), Ok(vec![]));
+        assert_eq!(sender.try_send(item(2)), Ok(vec![]));
+        assert_eq!(sender.try_send(item(3)), Err(TrySendError::Full(item(3))));
+
+        assert_eq!(drain(&mut receiver), vec![1, 2]);
+        assert_eq!(sender.try_send(item(3)), Ok(vec![]));
+    }
+
+    #[test]
+    #[should_panic(expected = "mailbox capacity must be at least 1")]
+    fn zero_capacity_is_rejected() {
+        let _ = channel::<Item>(Some(0), OverflowPolicy::Block);
+    }
+
+    #[tokio::test]
+    async fn blocked_send_resumes_once_space_is_free() {
+        let (sender, mut receiver) = channel(Some(1), OverflowPolicy::Block);
+        sender.try_send(item(1)).unwrap();
+
+        let blocked = tokio::spawn(async move { sender.send(item(2)).await });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!blocked.is_finished());
+
+        assert_eq!(receiver.recv().await, Some(item(1)));
+        assert_eq!(tokio::time::timeout(Duration::from_secs(1), blocked).await.unwrap().unwrap(), Ok(vec![]));
+        assert_eq!(receiver.recv().await, Some(item(2)));
+    }
+
+    #[tokio::test]
+    async fn close_wakes_blocked_senders() {
+        let (sender, mut receiver) = channel(Some(1), OverflowPolicy::Block);
+        sender.try_send(item(1)).unwrap();
+
+        let blocked = tokio::spawn({
+            let sender = sender.clone();
+            async move { sender.send(item(2)).await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        receiver.close();
+
+        let result = tokio::time::timeout(Duration::from_secs(1), blocked).await.unwrap().unwrap();
+        assert_eq!(result, Err(TrySendError::Closed(item(2))));
+    }
+
+    #[tokio::test]
+    async fn closed_mailbox_keeps_queued_values_receivable() {
+        let (sender, mut receiver) = channel(None, OverflowPolicy::Block);
+        sender.try_send(item(1)).unwrap();
+        sender.try_send(item(2)).unwrap();
+        sender.close();
+
+        assert_eq!(sender.try_send(item(3)), Err(TrySendError::Closed(item(3))));
+        assert_eq!(receiver.recv().await, Some(item(1)));
+        assert_eq!(receiver.recv().await, Some(item(2)));
+        assert_eq!(receiver.recv().await, None);
+    }
+
+    #[tokio::test]
+    async fn receiver_ends_once_all_senders_are_dropped() {
+        let (sender, mut receiver) = channel(None, OverflowPolicy::Block);
+        let clone = sender.clone();
+        sender.try_send(item(1)).unwrap();
+        drop(sender);
+
+        assert_eq!(receiver.recv().await, Some(item(1)));
+        assert!(receiver.recv().now_or_never().is_none());
+
+        drop(clone);
+        assert_eq!(receiver.recv().await, None);
+    }
+
+    #[test]
+    fn weak_sender_upgrades_only_while_open() {
+        let (sender, _receiver) = channel::<Item>(None, OverflowPolicy::Block);
+        let weak = sender.downgrade();
+
+        let upgraded = weak.upgrade().unwrap();
+        assert!(!sender.is_unique());
+        drop(upgraded);
+        assert!(sender.is_unique());
+
+        drop(sender);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn when_unique_runs_only_for_the_last_sender() {
+        let (sender, _receiver) = channel::<Item>(None, OverflowPolicy::Block);
+        let weak = sender.downgrade();
+
+        let upgraded = weak.upgrade().unwrap();
+        assert_eq!(sender.when_unique(|| 1), None);
+        drop(upgraded);
+        assert_eq!(sender.when_unique(|| 1), Some(1));
+    }
+
+    #[test]
+    fn weak_sender_sends_only_while_open() {
+        let (sender, mut receiver) = channel(None, OverflowPolicy::Block);
+        let weak = sender.downgrade();
+
+        assert!(!weak.is_closed());
+        assert_eq!(weak.try_send(item(1)), Ok(vec![]));
+        assert!(sender.is_unique());
+
+        drop(sender);
+        assert!(weak.is_closed());
+        assert_eq!(weak.try_send(item(2)), Err(TrySendError::Closed(item(2))));
+        assert_eq!(drain(&mut receiver), vec![1]);
+    }
+
+    #[test]
+    fn reject_refuses_new_values_when_full() {
+        let (sender, mut receiver) = channel(Some(1), OverflowPolicy::Reject);
+        sender.try_send(item(1)).unwrap();
+
+        assert_eq!(sender.try_send(item(2)), Err(TrySendError::Full(item(2))));
+        assert_eq!(sender.blocking_send(item(3)), Err(TrySendError::Full(item(3))));
+        assert_eq!(drain(&mut receiver), vec![1]);
+    }
+
+    #[test]
+    fn drop_newest_evicts_the_new_value() {
+        let (sender, mut receiver) = channel(Some(1), OverflowPolicy::DropNewest);
+        sender.try_send(item(1)).unwrap();
+
+        assert_eq!(sender.try_send(item(2)).map(values), Ok(vec![2]));
+        assert_eq!(drain(&mut receiver), vec![1]);
+    }
+
+    #[test]
+    fn drop_oldest_evicts_the_oldest_value() {
+        let (sender, mut receiver) = channel(Some(2), OverflowPolicy::DropOldest);
+        sender.try_send(item(1)).unwrap();
+        sender.try_send(item(2)).unwrap();
+
+        assert_eq!(sender.try_send(item(3)).map(values), Ok(vec![1]));
+        assert_eq!(drain(&mut receiver), vec![2, 3]);
+    }
+
+    #[test]
+    fn drop_oldest_evicts_from_the_lowest_lane_first() {
+        let (sender, mut receiver) = channel(Some(2), OverflowPolicy::DropOldest);
+        sender.try_send(Item { priority: Priority::High, ..item(1) }).unwrap();
+        sender.try_send(Item { priority: Priority::Low, ..item(2) }).unwrap();
+
+        assert_eq!(sender.try_send(item(3)).map(values), Ok(vec![2]));
+        assert_eq!(drain(&mut receiver), vec![1, 3]);
+    }
+
+    #[test]
+    fn keep_latest_evicts_queued_values_of_the_same_kind() {
+        let (sender, mut receiver) = channel(Some(3), OverflowPolicy::KeepLatest);
+        sender.try_send(item(1)).unwrap();
+        sender.try_send(other_item(2)).unwrap();
+        sender.try_send(item(3)).unwrap();
+
+        assert_eq!(sender.try_send(item(4)).map(values), Ok(vec![1, 3]));
+        assert_eq!(drain(&mut receiver), vec![2, 4]);
+    }
+
+    #[test]
+    fn keep_latest_falls_back_to_dropping_the_oldest_value() {
+        let (sender, mut receiver) = channel(Some(2), OverflowPolicy::KeepLatest);
+        sender.try_send(other_item(1)).unwrap();
+        sender.try_send(other_item(2)).unwrap();
+
+        assert_eq!(sender.try_send(item(3)).map(values), Ok(vec![1]));
+        assert_eq!(drain(&mut receiver), vec![2, 3]);
+    }
+
+    #[test]
+    fn overflow_policies_do_not_apply_below_capacity() {
+        for overflow in [OverflowPolicy::Reject, OverflowPolicy::DropNewest, OverflowPolicy::DropOldest, OverflowPolicy::KeepLatest] {
+            let (sender, mut receiver) = channel(Some(2), overflow);
+
+            assert_eq!(sender.try_send(item(1)), Ok(vec![]));
+            assert_eq!(sender.try_send(item(2)), Ok(vec![]));
+            assert_eq!(drain(&mut receiver), vec![1, 2]);
+        }
+    }
+
+    #[test]
+    fn weak_sender_does_not_keep_the_mailbox_open() {
+        let (sender, mut receiver) = channel::<Item>(None, OverflowPolicy::Block);
+        let _weak = sender.downgrade();
+        drop(sender);
+
+        assert_eq!(receiver.recv().now_or_never(), Some(None));
+    }
+
+    #[test]
+    fn higher_lanes_are_received_first_and_each_lane_is_fifo() {
+        let (sender, mut receiver) = channel(None, OverflowPolicy::Block);
+        sender.try_send(Item { priority: Priority::Low, ..item(1) }).unwrap();
+        sender.try_send(item(2)).unwrap();
+        sender.try_send(Item { priority: Priority::High, ..item(3) }).unwrap();
+        sender.try_send(Item { priority: Priority::Low, ..item(4) }).unwrap();
+        sender.try_send(Item { priority: Priority::High, ..item(5) }).unwrap();
+        sender.try_send(item(6)).unwrap();
+
+        assert_eq!(drain(&mut receiver), vec![3, 5, 2, 6, 1, 4]);
+    }
+}
@@ -2,4 +2,6 @@ pub mod container;
 pub mod resolvable;
 pub mod debug;
 pub mod lazy_old;
-pub mod lazy_cell;
\ No newline at end of file
+pub mod lazy_cell;
+pub mod mailbox;
+pub mod cancellation;
@@ -2,13 +2,17 @@ use crate::util::container::Container;
 use crate::util::debug::PointerDebug;
 use std::fmt::{Debug, Formatter};
 use std::future::Future;
+use std::mem::ManuallyDrop;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 
+type PollClosedFn = fn(data: *mut std::ffi::c_void, cx: &mut Context<'_>) -> Poll<()>;
+
 pub struct Resolver<M, T> {
     meta: M,
     data: *mut std::ffi::c_void,
     resolver: Option<fn(data: *mut std::ffi::c_void, value: Option<T>)>,
+    poll_closed: PollClosedFn,
 }
 
 impl<M, T> Resolver<M, T> {
@@ -17,6 +21,7 @@ impl<M, T> Resolver<M, T> {
             meta,
             data: std::ptr::null_mut(),
             resolver: Some(Self::noop_resolve),
+            poll_closed: noop_poll_closed,
         }
     }
 
@@ -26,6 +31,13 @@ impl<M, T> Resolver<M, T> {
         &self.meta
     }
 
+    /// Whether nobody waits for the value anymore. Never true for [`Resolver::noop`].
+    pub fn is_closed(&self) -> bool {
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+
+        (self.poll_closed)(self.data, &mut cx).is_ready()
+    }
+
     pub fn resolve(mut self, value: T) {
         (self.resolver.take().unwrap())(self.data, Some(value));
     }
@@ -34,6 +46,7 @@ impl<M, T> Resolver<M, T> {
         let thin = ThinResolver {
             data: self.data,
             resolver: self.resolver.unwrap(),
+            poll_closed: self.poll_closed,
         };
         let meta = unsafe { std::ptr::read(&self.meta) };
 
@@ -77,9 +90,15 @@ where
 pub struct ThinResolver<T> {
     data: *mut std::ffi::c_void,
     resolver: fn(data: *mut std::ffi::c_void, value: Option<T>),
+    poll_closed: PollClosedFn,
 }
 
 impl<T> ThinResolver<T> {
+    /// Resolves once nobody waits for the value anymore.
+    pub async fn closed(&mut self) {
+        std::future::poll_fn(move |cx| (self.poll_closed)(self.data, cx)).await
+    }
+
     pub fn resolve(self, value: T) {
         let data = self.data;
         let resolver = self.resolver;
@@ -107,6 +126,10 @@ impl<T> Debug for ThinResolver<T> {
     }
 }
 
+fn noop_poll_closed(_data: *mut std::ffi::c_void, _cx: &mut Context<'_>) -> Poll<()> {
+    Poll::Pending
+}
+
 struct TokioResolver;
 
 impl TokioResolver {
@@ -117,6 +140,13 @@ impl TokioResolver {
             drop(container.into_inner().send(value));
         }
     }
+
+    fn poll_closed<T>(data: *mut std::ffi::c_void, cx: &mut Context<'_>) -> Poll<()> {
+        let mut container =
+            ManuallyDrop::new(unsafe { Container::<tokio::sync::oneshot::Sender<T>>::from_raw(data) });
+
+        container.poll_closed(cx)
+    }
 }
 
 #[pin_project::pin_project]
@@ -141,6 +171,7 @@ impl<T> AsyncResolvable<T> {
             meta,
             data: container.into_raw(),
             resolver: Some(TokioResolver::resolve::<T>),
+            poll_closed: TokioResolver::poll_closed::<T>,
         };
 
         (resolvable, resolver)
@@ -175,6 +206,7 @@ impl<T> SyncResolvable<T> {
             meta,
             data: container.into_raw(),
             resolver: Some(TokioResolver::resolve::<T>),
+            poll_closed: TokioResolver::poll_closed::<T>,
         };
 
         (resolvable, resolver)